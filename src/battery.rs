@@ -0,0 +1,123 @@
+//! Estimation of the remaining battery capacity of coin cell powered tags.
+
+pub(crate) const DEFAULT_FULL_VOLTS: f64 = 3.0;
+pub(crate) const DEFAULT_LOW_PERCENT: f64 = 10.0;
+
+/// Highest voltage at which a battery is considered empty, see [`empty_volts`].
+const MAX_EMPTY_VOLTS: f64 = 2.5;
+
+/// Voltage of an empty CR2477 by temperature, as used for the low battery
+/// warning of the Ruuvi Station app. The cell voltage sags in the cold, so a
+/// tag in a freezer is not empty yet at a voltage that would be empty at
+/// room temperature.
+fn empty_volts(temperature_c: f64) -> f64 {
+    if temperature_c <= -20.0 {
+        2.0
+    } else if temperature_c < 0.0 {
+        2.3
+    } else {
+        MAX_EMPTY_VOLTS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryModel {
+    /// Voltage of a fresh battery
+    pub full_volts: f64,
+    /// Remaining percentage below which the battery is reported as low
+    pub low_percent: f64,
+}
+
+impl Default for BatteryModel {
+    fn default() -> Self {
+        Self {
+            full_volts: DEFAULT_FULL_VOLTS,
+            low_percent: DEFAULT_LOW_PERCENT,
+        }
+    }
+}
+
+impl BatteryModel {
+    /// Panics on values that would make the remaining capacity meaningless.
+    pub fn validate(&self) {
+        assert!(
+            self.full_volts > MAX_EMPTY_VOLTS,
+            "Battery full voltage must be above {} V: {}",
+            MAX_EMPTY_VOLTS,
+            self.full_volts
+        );
+        assert!(
+            (0.0..=100.0).contains(&self.low_percent),
+            "Battery low percentage must be between 0 and 100: {}",
+            self.low_percent
+        );
+    }
+
+    /// Remaining capacity in %, linear between the empty voltage at the
+    /// given temperature and the voltage of a fresh battery.
+    pub(crate) fn percent(&self, volts: f64, temperature_c: f64) -> f64 {
+        let empty = empty_volts(temperature_c);
+        ((volts - empty) / (self.full_volts - empty) * 100.0).clamp(0.0, 100.0)
+    }
+
+    pub(crate) fn is_low(&self, percent: f64) -> bool {
+        percent < self.low_percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentage_is_compensated_by_temperature() {
+        let model = BatteryModel::default();
+
+        // (V, °C, %)
+        for (volts, temperature, expected) in [
+            (3.0, 20.0, 100.0),
+            (2.75, 20.0, 50.0),
+            (2.5, 20.0, 0.0),
+            (2.4, 20.0, 0.0),
+            (2.4, -5.0, 14.29),
+            (2.4, -25.0, 40.0),
+            (3.1, -25.0, 100.0),
+        ] {
+            let percent = model.percent(volts, temperature);
+            assert!((percent - expected).abs() < 0.01, "{}", percent);
+        }
+    }
+
+    #[test]
+    fn low_battery_uses_configured_threshold() {
+        let model = BatteryModel {
+            low_percent: 25.0,
+            ..Default::default()
+        };
+
+        assert!(!model.is_low(model.percent(2.65, 20.0)));
+        assert!(model.is_low(model.percent(2.6, 20.0)));
+        // the same voltage is fine in a freezer
+        assert!(!model.is_low(model.percent(2.6, -18.0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Battery full voltage must be above")]
+    fn full_voltage_below_empty_voltage_panics() {
+        BatteryModel {
+            full_volts: 2.4,
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Battery low percentage must be between")]
+    fn out_of_range_low_percentage_panics() {
+        BatteryModel {
+            low_percent: 150.0,
+            ..Default::default()
+        }
+        .validate();
+    }
+}
@@ -1,18 +1,176 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::pin::pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use duration_string::DurationString;
 
 use bluer::DeviceEvent::{self, PropertyChanged};
-use bluer::DeviceProperty::{AdvertisingFlags, ManufacturerData, Rssi};
+use bluer::DeviceProperty::{AdvertisingFlags, ManufacturerData, Rssi, ServiceData};
 use bluer::monitor::{
-    Monitor, MonitorEvent, MonitorHandle, MonitorManager, Pattern, RssiSamplingPeriod, Type,
+    Monitor, MonitorEvent, MonitorManager, Pattern, RssiSamplingPeriod, Type,
     data_type::MANUFACTURER_SPECIFIC_DATA,
 };
-use bluer::{Adapter, Device, Session};
-use futures::{Stream, StreamExt};
+use bluer::{
+    Adapter, AdapterEvent, AdapterProperty, Address, Device, DiscoveryFilter, DiscoveryTransport,
+    Session, SessionEvent, Uuid,
+};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt, future};
 use tokio::sync::Mutex;
+use tokio::task::AbortHandle;
+use tokio::time;
 
+use crate::bthome::{BTHOME_SERVICE_UUID16, bthome_uuid};
+use crate::filter::DeviceFilter;
+use crate::liveness::DeviceLiveness;
 use crate::metrics::Metrics;
-use crate::ruuvi::handle_manufacturer_data;
+use crate::source::{
+    Advertisement, AdvertisementSender, AdvertisementSource, RUUVI_COMPANY_ID, spawn_source,
+};
+use crate::xiaomi::{ENVIRONMENTAL_SENSING_UUID16, environmental_sensing_uuid};
+
+/// How BlueZ is asked for advertisements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScanMode {
+    /// The advertisement monitor, or discovery if it cannot be registered.
+    #[default]
+    Auto,
+    /// Passive scanning with an advertisement monitor, which requires
+    /// bluetoothd to run with `--experimental`.
+    Monitor,
+    /// Regular discovery with duplicate advertisements reported.
+    Discovery,
+}
+
+impl ScanMode {
+    pub fn parse(value: &str) -> Self {
+        match value.trim() {
+            "auto" => Self::Auto,
+            "monitor" => Self::Monitor,
+            "discovery" => Self::Discovery,
+            _ => panic!("unknown scan mode: {}", value),
+        }
+    }
+}
+
+/// RSSI settings of the advertisement monitor. Without thresholds, every
+/// device BlueZ receives is found, regardless of its signal strength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorRssi {
+    /// A device is lost after `low_timeout` without a signal at least this strong
+    pub low_threshold: Option<i16>,
+    /// A device is found after `high_timeout` of signals at least this strong
+    pub high_threshold: Option<i16>,
+    pub low_timeout: Option<Duration>,
+    pub high_timeout: Option<Duration>,
+    /// How often the RSSI of a device is updated
+    pub sampling_period: RssiSamplingPeriod,
+}
+
+impl Default for MonitorRssi {
+    fn default() -> Self {
+        Self {
+            low_threshold: None,
+            high_threshold: None,
+            low_timeout: None,
+            high_timeout: None,
+            sampling_period: RssiSamplingPeriod::First,
+        }
+    }
+}
+
+impl MonitorRssi {
+    /// Panics on values BlueZ would reject when registering the monitor.
+    pub fn validate(&self) {
+        for threshold in [self.low_threshold, self.high_threshold]
+            .into_iter()
+            .flatten()
+        {
+            assert!(
+                (-127..=20).contains(&threshold),
+                "RSSI threshold must be between -127 and 20 dBm: {}",
+                threshold
+            );
+        }
+        for timeout in [self.low_timeout, self.high_timeout].into_iter().flatten() {
+            assert!(
+                (1..=300).contains(&timeout.as_secs()),
+                "RSSI timeout must be between 1 and 300 seconds: {:?}",
+                timeout
+            );
+        }
+        if let RssiSamplingPeriod::Period(period) = self.sampling_period {
+            assert!(
+                (MIN_SAMPLING_PERIOD..=MAX_SAMPLING_PERIOD).contains(&period),
+                "RSSI sampling period must be between 100ms and 25.4s: {:?}",
+                period
+            );
+        }
+        if let (Some(low), Some(high)) = (self.low_threshold, self.high_threshold) {
+            assert!(
+                low <= high,
+                "RSSI low threshold {} is above the high threshold {}",
+                low,
+                high
+            );
+        }
+    }
+}
+
+/// Sampling periods are sent to BlueZ in units of 100ms, 0 and 255 are
+/// reserved for `All` and `First`.
+const MIN_SAMPLING_PERIOD: Duration = Duration::from_millis(100);
+const MAX_SAMPLING_PERIOD: Duration = Duration::from_millis(25_400);
+
+/// Parses `first`, `all`, or a period like `5s`, which BlueZ rounds to 100ms.
+pub fn parse_sampling_period(value: &str) -> RssiSamplingPeriod {
+    match value.trim() {
+        "first" => RssiSamplingPeriod::First,
+        "all" => RssiSamplingPeriod::All,
+        period => RssiSamplingPeriod::Period(period.parse::<DurationString>().unwrap().into()),
+    }
+}
+
+/// Which Bluetooth adapters to scan with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelection {
+    /// Every adapter, including ones plugged in later.
+    All,
+    Named(Vec<String>),
+}
+
+impl AdapterSelection {
+    /// Parses `all` or a comma separated list like `hci0,hci1`.
+    pub fn parse(value: &str) -> Self {
+        if value.trim() == "all" {
+            return Self::All;
+        }
+        let names: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if names.is_empty() {
+            panic!("no adapter given: {:?}", value);
+        }
+        Self::Named(names)
+    }
+}
+
+/// A sensor coming into or going out of range, regardless of the scan mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanEvent {
+    Found(Address),
+    Lost(Address),
+}
+
+type ScanEvents = BoxStream<'static, ScanEvent>;
+
+/// Not among the `data_type` constants of bluer.
+const SERVICE_DATA_16_BIT_UUID: u8 = 0x16;
 
 fn manufacturer_pattern() -> Pattern {
     let data_type: u8 = MANUFACTURER_SPECIFIC_DATA;
@@ -25,6 +183,23 @@ fn manufacturer_pattern() -> Pattern {
     }
 }
 
+fn service_data_pattern(uuid16: u16) -> Pattern {
+    Pattern {
+        data_type: SERVICE_DATA_16_BIT_UUID,
+        start_position: 0x00,
+        content: uuid16.to_le_bytes().to_vec(),
+    }
+}
+
+fn bthome_pattern() -> Pattern {
+    service_data_pattern(BTHOME_SERVICE_UUID16)
+}
+
+/// Xiaomi thermometers running the pvvx or ATC1441 firmware.
+fn environmental_sensing_pattern() -> Pattern {
+    service_data_pattern(ENVIRONMENTAL_SENSING_UUID16)
+}
+
 async fn init_adapter(session: &Session, preferred: Option<&str>) -> bluer::Result<Adapter> {
     choose_adapter(
         preferred,
@@ -34,6 +209,23 @@ async fn init_adapter(session: &Session, preferred: Option<&str>) -> bluer::Resu
     .await
 }
 
+/// Discovery reports every device in range, so sensors are recognized by the
+/// same data the monitor patterns match on.
+fn has_sensor_data(
+    manufacturer_data: &HashMap<u16, Vec<u8>>,
+    service_data: &HashMap<Uuid, Vec<u8>>,
+) -> bool {
+    manufacturer_data.contains_key(&RUUVI_COMPANY_ID)
+        || service_data.contains_key(&bthome_uuid())
+        || service_data.contains_key(&environmental_sensing_uuid())
+}
+
+async fn is_sensor(dev: &Device) -> bluer::Result<bool> {
+    let manufacturer_data = dev.manufacturer_data().await?.unwrap_or_default();
+    let service_data = dev.service_data().await?.unwrap_or_default();
+    Ok(has_sensor_data(&manufacturer_data, &service_data))
+}
+
 fn format_device_address(address: &bluer::Address) -> String {
     format!(
         "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
@@ -41,63 +233,544 @@ fn format_device_address(address: &bluer::Address) -> String {
     )
 }
 
-pub(crate) async fn setup_adapter_monitor(
+/// Delays between attempts to set up the adapter again.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Shorter than the default idle timeout, so that the adapter metrics are
+/// not removed, also while backing off.
+const ADAPTER_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Exponential backoff, reset after a successful connection.
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            next: INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl Backoff {
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.next = INITIAL_BACKOFF;
+    }
+}
+
+/// Advertisements received from BlueZ, through an advertisement monitor or
+/// discovery depending on the `ScanMode`. Scanning is set up again whenever
+/// bluetoothd restarts or the adapter is powered off or unplugged.
+pub(crate) struct BluezMonitorSource {
+    preferred: Option<String>,
+    mode: ScanMode,
+    rssi: MonitorRssi,
+    filter: DeviceFilter,
+    metrics: Metrics,
+    liveness: DeviceLiveness,
+}
+
+impl BluezMonitorSource {
+    /// Devices rejected by `filter` are dropped before their events are
+    /// subscribed to, counting the dropped frames in `metrics`. Devices lost
+    /// by BlueZ are marked down in `liveness`.
+    pub(crate) fn new(
+        preferred: Option<&str>,
+        mode: ScanMode,
+        rssi: MonitorRssi,
+        filter: DeviceFilter,
+        metrics: Metrics,
+        liveness: DeviceLiveness,
+    ) -> Self {
+        Self {
+            preferred: preferred.map(str::to_string),
+            mode,
+            rssi,
+            filter,
+            metrics,
+            liveness,
+        }
+    }
+
+    /// The `adapter` label, stable across reconnects.
+    fn adapter_label(&self) -> &str {
+        self.preferred.as_deref().unwrap_or("default")
+    }
+
+    /// Listens until the scan stream ends or the adapter goes away.
+    async fn listen(
+        &self,
+        scanner: AdapterScanner,
+        sink: &AdvertisementSender,
+    ) -> bluer::Result<()> {
+        let AdapterScanner {
+            session,
+            adapter,
+            events,
+            monitor_manager: _monitor_manager,
+        } = scanner;
+        let active_devices = Arc::new(ActiveDevices::new(adapter.name(), self.metrics.clone()));
+        let result = tokio::select! {
+            result = watch_adapter(&session, &adapter) => result,
+            result = scan_and_listen(
+                adapter.clone(),
+                events,
+                active_devices.clone(),
+                sink.clone(),
+                &self.filter,
+                &self.metrics,
+                &self.liveness,
+            ) => result,
+        };
+        // the device tasks would otherwise outlive the adapter
+        active_devices.close().await;
+        result
+    }
+
+    /// Sets up the adapter again with a backoff whenever it is lost, until
+    /// the sink is closed. `up` tracks whether it is currently listening.
+    async fn supervise(&self, sink: &AdvertisementSender, up: &AtomicBool) -> bluer::Result<()> {
+        let label = self.adapter_label();
+        let mut backoff = Backoff::default();
+        let mut connected_before = false;
+        loop {
+            match setup_adapter_scanner(self.preferred.as_deref(), self.mode, &self.rssi).await {
+                Ok(scanner) => {
+                    if connected_before {
+                        println!("Reconnected to adapter {}", label);
+                        self.metrics.inc_adapter_reconnects(label, 1);
+                    }
+                    connected_before = true;
+                    backoff.reset();
+                    up.store(true, Ordering::Relaxed);
+                    self.metrics.set_adapter_up(label, true);
+                    if let Err(err) = self.listen(scanner, sink).await {
+                        eprintln!("Error listening on adapter {}: {}", label, err);
+                    }
+                    if sink.is_closed() {
+                        return Ok(());
+                    }
+                    eprintln!("Lost adapter {}", label);
+                }
+                Err(err) => eprintln!("Failed to set up adapter {}: {}", label, err),
+            }
+            up.store(false, Ordering::Relaxed);
+            self.metrics.set_adapter_up(label, false);
+            let delay = backoff.next_delay();
+            println!("Setting up adapter {} again in {:?}", label, delay);
+            time::sleep(delay).await;
+        }
+    }
+}
+
+impl AdvertisementSource for BluezMonitorSource {
+    fn name(&self) -> &'static str {
+        "bluez-monitor"
+    }
+
+    async fn run(self, sink: AdvertisementSender) -> bluer::Result<()> {
+        let up = AtomicBool::new(false);
+        let refresh = async {
+            let mut interval = time::interval(ADAPTER_REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.metrics
+                    .refresh_adapter(self.adapter_label(), up.load(Ordering::Relaxed));
+            }
+        };
+        tokio::select! {
+            result = self.supervise(&sink, &up) => result,
+            _ = refresh => Ok(()),
+        }
+    }
+}
+
+/// Scans with every adapter, each supervised by its own `BluezMonitorSource`.
+/// Adapters plugged in later are picked up as well.
+pub(crate) struct AllAdaptersSource {
+    mode: ScanMode,
+    rssi: MonitorRssi,
+    filter: DeviceFilter,
+    metrics: Metrics,
+    liveness: DeviceLiveness,
+}
+
+impl AllAdaptersSource {
+    pub(crate) fn new(
+        mode: ScanMode,
+        rssi: MonitorRssi,
+        filter: DeviceFilter,
+        metrics: Metrics,
+        liveness: DeviceLiveness,
+    ) -> Self {
+        Self {
+            mode,
+            rssi,
+            filter,
+            metrics,
+            liveness,
+        }
+    }
+
+    fn start(&self, started: &mut HashSet<String>, name: String, sink: &AdvertisementSender) {
+        if started.contains(&name) {
+            return;
+        }
+        println!("Scanning with adapter {}", name);
+        let source = BluezMonitorSource::new(
+            Some(name.as_str()),
+            self.mode,
+            self.rssi,
+            self.filter.clone(),
+            self.metrics.clone(),
+            self.liveness.clone(),
+        );
+        spawn_source(source, sink.clone());
+        started.insert(name);
+    }
+}
+
+impl AdvertisementSource for AllAdaptersSource {
+    fn name(&self) -> &'static str {
+        "bluez-all-adapters"
+    }
+
+    async fn run(self, sink: AdvertisementSender) -> bluer::Result<()> {
+        let session = Session::new().await?;
+        // subscribe first, to not miss adapters added in between
+        let events = session.events().await?;
+        let mut events = pin!(events);
+        let mut started = HashSet::new();
+        // fails while bluetoothd is not running, its adapters are added once it is
+        match session.adapter_names().await {
+            Ok(names) => {
+                for name in names {
+                    self.start(&mut started, name, &sink);
+                }
+            }
+            Err(err) => eprintln!("Failed to list adapters: {}", err),
+        }
+        while let Some(event) = events.next().await {
+            if let SessionEvent::AdapterAdded(name) = event {
+                self.start(&mut started, name, &sink);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns when the adapter is powered off or removed, or bluetoothd goes away.
+async fn watch_adapter(session: &Session, adapter: &Adapter) -> bluer::Result<()> {
+    let adapter_events = adapter.events().await?;
+    let session_events = session.events().await?;
+    let mut adapter_events = pin!(adapter_events);
+    let mut session_events = pin!(session_events);
+    loop {
+        tokio::select! {
+            event = adapter_events.next() => match event {
+                Some(AdapterEvent::PropertyChanged(AdapterProperty::Powered(false))) | None => {
+                    return Ok(());
+                }
+                _ => {}
+            },
+            event = session_events.next() => match event {
+                Some(SessionEvent::AdapterRemoved(name)) if name == adapter.name() => return Ok(()),
+                None => return Ok(()),
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Devices with a running event task, so that each device is subscribed to
+/// only once. The number of devices is exported per adapter.
+pub(crate) struct ActiveDevices {
+    tasks: Mutex<HashMap<String, Option<AbortHandle>>>,
+    /// Set once the adapter is gone, so that late tasks do not overwrite the
+    /// count of the next `ActiveDevices` of the same adapter.
+    closed: AtomicBool,
+    adapter: String,
+    metrics: Metrics,
+}
+
+impl ActiveDevices {
+    pub(crate) fn new(adapter: &str, metrics: Metrics) -> Self {
+        Self {
+            tasks: Mutex::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+            adapter: adapter.to_string(),
+            metrics,
+        }
+    }
+
+    /// Returns false if the device is already active.
+    async fn mark_active(&self, addr: &str) -> bool {
+        let mut tasks = self.tasks.lock().await;
+        if tasks.contains_key(addr) {
+            return false;
+        }
+        tasks.insert(addr.to_string(), None);
+        self.metrics.set_tracked_devices(&self.adapter, tasks.len());
+        true
+    }
+
+    /// Remembers the event task of a device, unless it already finished.
+    async fn set_task(&self, addr: &str, task: AbortHandle) {
+        if let Some(entry) = self.tasks.lock().await.get_mut(addr) {
+            *entry = Some(task);
+        }
+    }
+
+    async fn remove(&self, addr: &str) -> Option<AbortHandle> {
+        let mut tasks = self.tasks.lock().await;
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+        let task = tasks.remove(addr).flatten();
+        self.metrics.set_tracked_devices(&self.adapter, tasks.len());
+        task
+    }
+
+    /// Stops listening to a device which BlueZ reported as lost.
+    async fn cancel(&self, addr: &str) {
+        if let Some(task) = self.remove(addr).await {
+            task.abort();
+        }
+    }
+
+    /// Stops listening to all devices when the adapter is gone.
+    async fn close(&self) {
+        let mut tasks = self.tasks.lock().await;
+        self.closed.store(true, Ordering::SeqCst);
+        for task in tasks.drain().filter_map(|(_, task)| task) {
+            task.abort();
+        }
+        self.metrics.set_tracked_devices(&self.adapter, 0);
+    }
+}
+
+/// Scanning on an adapter, active as long as this is kept.
+struct AdapterScanner {
+    session: Session,
+    adapter: Adapter,
+    events: ScanEvents,
+    /// Only set when scanning with an advertisement monitor.
+    monitor_manager: Option<MonitorManager>,
+}
+
+async fn setup_adapter_scanner(
     preferred: Option<&str>,
-) -> bluer::Result<(Adapter, MonitorHandle, MonitorManager)> {
-    let pattern = manufacturer_pattern();
+    mode: ScanMode,
+    rssi: &MonitorRssi,
+) -> bluer::Result<AdapterScanner> {
     let session = bluer::Session::new().await?;
     let adapter = init_adapter(&session, preferred).await?;
+    adapter.set_powered(true).await?;
+    let (events, monitor_manager) = match mode {
+        ScanMode::Monitor => {
+            let (events, monitor_manager) = register_monitor(&adapter, rssi).await?;
+            (events, Some(monitor_manager))
+        }
+        ScanMode::Discovery => (discover(&adapter, rssi).await?, None),
+        ScanMode::Auto => match register_monitor(&adapter, rssi).await {
+            Ok((events, monitor_manager)) => (events, Some(monitor_manager)),
+            Err(err) => {
+                eprintln!(
+                    "Advertisement monitor unavailable on adapter {} ({}), falling back to discovery",
+                    adapter.name(),
+                    err
+                );
+                (discover(&adapter, rssi).await?, None)
+            }
+        },
+    };
+
+    Ok(AdapterScanner {
+        session,
+        adapter,
+        events,
+        monitor_manager,
+    })
+}
+
+fn monitor(rssi: &MonitorRssi) -> Monitor {
+    let patterns = vec![
+        manufacturer_pattern(),
+        bthome_pattern(),
+        environmental_sensing_pattern(),
+    ];
+    Monitor {
+        monitor_type: Type::OrPatterns,
+        rssi_low_threshold: rssi.low_threshold,
+        rssi_high_threshold: rssi.high_threshold,
+        rssi_low_timeout: rssi.low_timeout,
+        rssi_high_timeout: rssi.high_timeout,
+        rssi_sampling_period: Some(rssi.sampling_period),
+        patterns: Some(patterns),
+        ..Default::default()
+    }
+}
+
+/// Fails unless bluetoothd runs with experimental features enabled.
+async fn register_monitor(
+    adapter: &Adapter,
+    rssi: &MonitorRssi,
+) -> bluer::Result<(ScanEvents, MonitorManager)> {
+    let monitor = monitor(rssi);
     println!(
-        "Running le_passive_scan on adapter {} with or-pattern {:?}",
+        "Running le_passive_scan on adapter {} with or-patterns {:?} and {:?}",
         adapter.name(),
-        pattern
+        monitor.patterns,
+        rssi
     );
-    adapter.set_powered(true).await?;
     let monitor_manager = adapter.monitor().await?;
-    let monitor_handle = monitor_manager
-        .register(Monitor {
-            monitor_type: Type::OrPatterns,
-            rssi_low_threshold: None,
-            rssi_high_threshold: None,
-            rssi_low_timeout: None,
-            rssi_high_timeout: None,
-            rssi_sampling_period: Some(RssiSamplingPeriod::First),
-            patterns: Some(vec![pattern]),
+    let monitor_handle = monitor_manager.register(monitor).await?;
+    let events = monitor_handle
+        .filter_map(|event| {
+            future::ready(match event {
+                MonitorEvent::DeviceFound(devid) => Some(ScanEvent::Found(devid.device)),
+                MonitorEvent::DeviceLost(devid) => Some(ScanEvent::Lost(devid.device)),
+                _ => None,
+            })
+        })
+        .boxed();
+    Ok((events, monitor_manager))
+}
+
+/// Active discovery of LE devices, reporting every advertisement instead of
+/// only changed ones. Devices without sensor data are skipped, as are devices
+/// weaker than the high RSSI threshold of the monitor.
+async fn discover(adapter: &Adapter, rssi: &MonitorRssi) -> bluer::Result<ScanEvents> {
+    println!("Running LE discovery on adapter {}", adapter.name());
+    adapter
+        .set_discovery_filter(DiscoveryFilter {
+            transport: DiscoveryTransport::Le,
+            duplicate_data: true,
+            rssi: rssi.high_threshold,
             ..Default::default()
         })
         .await?;
+    let discovery = adapter.discover_devices().await?;
+    let adapter = adapter.clone();
+    let mut sensors = DiscoveredSensors::default();
+    let events = discovery
+        .then(move |event| {
+            let adapter = adapter.clone();
+            async move {
+                let sensor = match &event {
+                    AdapterEvent::DeviceAdded(address) => match adapter.device(*address) {
+                        Ok(dev) => is_sensor(&dev).await.unwrap_or(false),
+                        Err(_) => false,
+                    },
+                    _ => false,
+                };
+                (event, sensor)
+            }
+        })
+        .filter_map(move |(event, sensor)| future::ready(sensors.scan_event(event, sensor)))
+        .boxed();
+    Ok(events)
+}
 
-    Ok((adapter, monitor_handle, monitor_manager))
+/// Sensors reported as found by discovery, so that only those are reported as
+/// lost again and not every other device leaving the range.
+#[derive(Debug, Default)]
+struct DiscoveredSensors {
+    found: HashSet<Address>,
 }
 
-pub(crate) async fn scan_and_listen(
+impl DiscoveredSensors {
+    fn scan_event(&mut self, event: AdapterEvent, sensor: bool) -> Option<ScanEvent> {
+        match event {
+            AdapterEvent::DeviceAdded(address) if sensor => {
+                self.found.insert(address);
+                Some(ScanEvent::Found(address))
+            }
+            AdapterEvent::DeviceRemoved(address) if self.found.remove(&address) => {
+                Some(ScanEvent::Lost(address))
+            }
+            _ => None,
+        }
+    }
+}
+
+async fn scan_and_listen(
     adapter: Adapter,
-    mut monitor_handle: MonitorHandle,
-    metrics: Metrics,
+    mut events: ScanEvents,
+    active_devices: Arc<ActiveDevices>,
+    sink: AdvertisementSender,
+    filter: &DeviceFilter,
+    metrics: &Metrics,
+    liveness: &DeviceLiveness,
 ) -> bluer::Result<()> {
-    let active_devices = Arc::new(Mutex::new(HashSet::new()));
-    while let Some(mevt) = &monitor_handle.next().await {
-        if let MonitorEvent::DeviceFound(devid) = mevt {
-            #[cfg(debug_assertions)]
-            println!("Discovered device {:?}", devid);
-            let dev = adapter.device(devid.device)?;
-            let addr = format_device_address(&dev.address());
-            if let Some(rssi) = dev.rssi().await? {
-                metrics.set_signal_rssi(&addr, rssi as f64);
+    while let Some(event) = events.next().await {
+        match event {
+            ScanEvent::Found(address) => {
                 #[cfg(debug_assertions)]
-                println!("{:?} RSSI: {}", dev, rssi);
-            }
+                println!("Discovered device {}", address);
+                let dev = adapter.device(address)?;
+                let addr = format_device_address(&dev.address());
+                if let Some(reason) = filter.rejects(&addr) {
+                    metrics.inc_frames_dropped(reason);
+                    continue;
+                }
+                liveness.found(&addr, adapter.name());
+                // a device vanishing from D-Bus in the meantime must not tear down the adapter
+                let rssi = match dev.rssi().await {
+                    Ok(rssi) => rssi,
+                    Err(err) => {
+                        eprintln!("Error reading RSSI of {}: {}", addr, err);
+                        continue;
+                    }
+                };
+                if let Some(rssi) = rssi {
+                    let mut advertisement = Advertisement::new(addr.as_str());
+                    advertisement.adapter = Some(adapter.name().to_string());
+                    advertisement.rssi = Some(rssi);
+                    if sink.send(advertisement).await.is_err() {
+                        break;
+                    }
+                    #[cfg(debug_assertions)]
+                    println!("{:?} RSSI: {}", dev, rssi);
+                }
 
-            if !mark_active(&active_devices, &addr).await {
-                continue;
-            }
+                if !active_devices.mark_active(&addr).await {
+                    continue;
+                }
 
-            seed_from_properties(&dev, &metrics, &addr).await;
+                // devices only known from the BlueZ cache have no RSSI and stale data
+                if rssi.is_some() {
+                    seed_from_properties(&dev, &sink, &addr).await;
+                }
 
-            let active_devices = active_devices.clone();
-            tokio::spawn(async move {
-                handle_device_events(dev, metrics, addr, active_devices).await;
-            });
+                let task = tokio::spawn(handle_device_events(
+                    dev,
+                    sink.clone(),
+                    addr.clone(),
+                    active_devices.clone(),
+                ));
+                active_devices.set_task(&addr, task.abort_handle()).await;
+            }
+            ScanEvent::Lost(address) => {
+                let addr = format_device_address(&address);
+                if filter.rejects(&addr).is_some() {
+                    continue;
+                }
+                #[cfg(debug_assertions)]
+                println!("Lost device {}", addr);
+                active_devices.cancel(&addr).await;
+                if liveness.lost(&addr, adapter.name()) {
+                    metrics.set_device_up(&addr, false);
+                }
+            }
         }
     }
     Ok(())
@@ -105,13 +778,20 @@ pub(crate) async fn scan_and_listen(
 
 async fn handle_device_events(
     dev: Device,
-    metrics: Metrics,
+    sink: AdvertisementSender,
     addr: String,
-    active_devices: Arc<Mutex<HashSet<String>>>,
+    active_devices: Arc<ActiveDevices>,
 ) {
     let result: bluer::Result<()> = async {
         let mut events = dev.events().await?;
-        process_events_stream(&mut events, metrics, &addr, active_devices.clone()).await;
+        process_events_stream(
+            &mut events,
+            sink,
+            dev.adapter_name(),
+            &addr,
+            active_devices.clone(),
+        )
+        .await;
         Ok(())
     }
     .await;
@@ -120,54 +800,56 @@ async fn handle_device_events(
         eprintln!("Error processing device {}: {}", addr, err);
     }
 
-    active_devices.lock().await.remove(&addr);
+    active_devices.remove(&addr).await;
 }
 
-async fn seed_from_properties(dev: &Device, metrics: &Metrics, addr: &str) {
+async fn seed_from_properties(dev: &Device, sink: &AdvertisementSender, addr: &str) {
     #[cfg(debug_assertions)]
     println!("All properties: {:?}", dev.all_properties().await.unwrap());
-    seed_from_properties_iter(
-        dev.all_properties().await.unwrap(),
-        metrics,
-        addr,
-        Some(dev),
-    );
-}
-
-async fn mark_active(active_devices: &Arc<Mutex<HashSet<String>>>, addr: &str) -> bool {
-    let mut active = active_devices.lock().await;
-    if !active.insert(addr.to_string()) {
-        return false;
+    if let Some(mut advertisement) =
+        seed_from_properties_iter(dev.all_properties().await.unwrap(), addr, Some(dev))
+    {
+        advertisement.adapter = Some(dev.adapter_name().to_string());
+        let _ = sink.send(advertisement).await;
     }
-    true
 }
 
-fn handle_device_property(
-    metrics: &Metrics,
+fn advertisement_from_property(
     addr: &str,
     event: DeviceEvent,
     _dev: Option<&Device>,
-) {
+) -> Option<Advertisement> {
     match event {
-        PropertyChanged(ManufacturerData(data)) => match data.get(&0x0499) {
-            Some(value) => handle_manufacturer_data(metrics, addr, value),
-            None => eprintln!("No data found"),
-        },
+        PropertyChanged(ManufacturerData(data)) => {
+            let mut advertisement = Advertisement::new(addr);
+            advertisement.manufacturer_data = data;
+            Some(advertisement)
+        }
+        PropertyChanged(ServiceData(data)) => {
+            let mut advertisement = Advertisement::new(addr);
+            advertisement.service_data = data;
+            Some(advertisement)
+        }
         PropertyChanged(Rssi(rssi)) => {
-            metrics.set_signal_rssi(addr, rssi as f64);
             #[cfg(debug_assertions)]
             if let Some(dev) = _dev {
                 println!("{:?} RSSI: {}", dev, rssi);
             }
+            let mut advertisement = Advertisement::new(addr);
+            advertisement.rssi = Some(rssi);
+            Some(advertisement)
         }
-        PropertyChanged(AdvertisingFlags(_flags)) =>
-        {
+        PropertyChanged(AdvertisingFlags(_flags)) => {
             #[cfg(debug_assertions)]
             if let Some(dev) = _dev {
                 println!("{:?} AdvertisingFlags: {:?}", dev, _flags);
             }
+            None
+        }
+        _ => {
+            eprintln!("Unknown event: {:?}", event);
+            None
         }
-        _ => eprintln!("Unknown event: {:?}", event),
     }
 }
 
@@ -192,39 +874,59 @@ where
 
 async fn process_events_stream<S>(
     events: &mut S,
-    metrics: Metrics,
+    sink: AdvertisementSender,
+    adapter: &str,
     addr: &str,
-    active_devices: Arc<Mutex<HashSet<String>>>,
+    active_devices: Arc<ActiveDevices>,
 ) where
     S: Stream<Item = DeviceEvent> + Unpin,
 {
     while let Some(ev) = events.next().await {
-        handle_device_property(&metrics, addr, ev, None);
-        metrics.update_rust_and_process_start_time(); // otherwise the metrics are removed after the idle timeout
+        if let Some(mut advertisement) = advertisement_from_property(addr, ev, None) {
+            advertisement.adapter = Some(adapter.to_string());
+            if sink.send(advertisement).await.is_err() {
+                break;
+            }
+        }
     }
-    active_devices.lock().await.remove(addr);
+    active_devices.remove(addr).await;
 }
 
-fn seed_from_properties_iter<I>(properties: I, metrics: &Metrics, addr: &str, dev: Option<&Device>)
+fn seed_from_properties_iter<I>(
+    properties: I,
+    addr: &str,
+    dev: Option<&Device>,
+) -> Option<Advertisement>
 where
     I: IntoIterator<Item = bluer::DeviceProperty>,
 {
     for property in properties {
-        if let ManufacturerData(data) = property {
-            handle_device_property(metrics, addr, PropertyChanged(ManufacturerData(data)), dev);
-            break;
+        match property {
+            ManufacturerData(data) => {
+                return advertisement_from_property(
+                    addr,
+                    PropertyChanged(ManufacturerData(data)),
+                    dev,
+                );
+            }
+            ServiceData(data) => {
+                return advertisement_from_property(addr, PropertyChanged(ServiceData(data)), dev);
+            }
+            _ => {}
         }
     }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ruuvi::Decoder;
+    use crate::source::handle_advertisement;
     use crate::test_utils::metrics::{clear, counter_value, gauge_value, take_snapshot};
-    use bluer::ErrorKind;
+    use bluer::{ErrorKind, UuidExt};
     use futures::stream;
     use std::sync::Arc as StdArc;
-    use std::sync::atomic::{AtomicBool, Ordering};
 
     #[test]
     fn manufacturer_pattern_matches_ruuvi_prefix() {
@@ -235,6 +937,181 @@ mod tests {
         assert_eq!(vec![0x99, 0x04], pattern.content);
     }
 
+    #[test]
+    fn bthome_pattern_matches_service_uuid() {
+        let pattern = bthome_pattern();
+
+        assert_eq!(0x16, pattern.data_type);
+        assert_eq!(0, pattern.start_position);
+        assert_eq!(vec![0xD2, 0xFC], pattern.content);
+    }
+
+    #[test]
+    fn environmental_sensing_pattern_matches_service_uuid() {
+        let pattern = environmental_sensing_pattern();
+
+        assert_eq!(0x16, pattern.data_type);
+        assert_eq!(vec![0x1A, 0x18], pattern.content);
+    }
+
+    #[test]
+    fn monitor_uses_configured_rssi_settings() {
+        let default = monitor(&MonitorRssi::default());
+        assert_eq!(None, default.rssi_low_threshold);
+        assert_eq!(None, default.rssi_high_timeout);
+        assert_eq!(
+            Some(RssiSamplingPeriod::First),
+            default.rssi_sampling_period
+        );
+        assert_eq!(3, default.patterns.unwrap().len());
+
+        let configured = monitor(&MonitorRssi {
+            low_threshold: Some(-90),
+            high_threshold: Some(-75),
+            low_timeout: Some(Duration::from_secs(30)),
+            high_timeout: Some(Duration::from_secs(2)),
+            sampling_period: parse_sampling_period("5s"),
+        });
+        assert_eq!(Some(-90), configured.rssi_low_threshold);
+        assert_eq!(Some(-75), configured.rssi_high_threshold);
+        assert_eq!(Some(Duration::from_secs(30)), configured.rssi_low_timeout);
+        assert_eq!(Some(Duration::from_secs(2)), configured.rssi_high_timeout);
+        assert_eq!(
+            Some(RssiSamplingPeriod::Period(Duration::from_secs(5))),
+            configured.rssi_sampling_period
+        );
+    }
+
+    #[test]
+    fn sampling_periods_are_parsed() {
+        assert_eq!(RssiSamplingPeriod::First, parse_sampling_period("first"));
+        assert_eq!(RssiSamplingPeriod::All, parse_sampling_period("all"));
+        assert_eq!(
+            RssiSamplingPeriod::Period(Duration::from_millis(500)),
+            parse_sampling_period("500ms")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "RSSI threshold must be between")]
+    fn out_of_range_rssi_threshold_panics() {
+        MonitorRssi {
+            high_threshold: Some(-130),
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "RSSI sampling period must be between")]
+    fn out_of_range_sampling_period_panics() {
+        MonitorRssi {
+            sampling_period: parse_sampling_period("60s"),
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "above the high threshold")]
+    fn low_threshold_above_high_threshold_panics() {
+        MonitorRssi {
+            low_threshold: Some(-60),
+            high_threshold: Some(-80),
+            ..Default::default()
+        }
+        .validate();
+    }
+
+    #[test]
+    fn adapter_selection_is_parsed() {
+        assert_eq!(AdapterSelection::All, AdapterSelection::parse("all"));
+        assert_eq!(
+            AdapterSelection::Named(vec!["hci0".to_string()]),
+            AdapterSelection::parse("hci0")
+        );
+        assert_eq!(
+            AdapterSelection::Named(vec!["hci0".to_string(), "hci1".to_string()]),
+            AdapterSelection::parse("hci0, hci1,")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no adapter given")]
+    fn empty_adapter_selection_panics() {
+        AdapterSelection::parse(" , ");
+    }
+
+    #[test]
+    fn scan_modes_are_parsed() {
+        assert_eq!(ScanMode::Auto, ScanMode::parse("auto"));
+        assert_eq!(ScanMode::Monitor, ScanMode::parse("monitor"));
+        assert_eq!(ScanMode::Discovery, ScanMode::parse(" discovery "));
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown scan mode")]
+    fn unknown_scan_mode_panics() {
+        ScanMode::parse("passive");
+    }
+
+    #[test]
+    fn only_discovered_sensors_are_lost() {
+        let sensor = bluer::Address([0x11, 0, 0, 0, 0, 1]);
+        let phone = bluer::Address([0x11, 0, 0, 0, 0, 2]);
+        let mut sensors = DiscoveredSensors::default();
+
+        assert_eq!(
+            Some(ScanEvent::Found(sensor)),
+            sensors.scan_event(AdapterEvent::DeviceAdded(sensor), true)
+        );
+        assert_eq!(
+            None,
+            sensors.scan_event(AdapterEvent::DeviceAdded(phone), false)
+        );
+        assert_eq!(
+            None,
+            sensors.scan_event(AdapterEvent::DeviceRemoved(phone), false)
+        );
+        assert_eq!(
+            Some(ScanEvent::Lost(sensor)),
+            sensors.scan_event(AdapterEvent::DeviceRemoved(sensor), false)
+        );
+        assert_eq!(
+            None,
+            sensors.scan_event(AdapterEvent::DeviceRemoved(sensor), false)
+        );
+    }
+
+    #[test]
+    fn discovered_devices_are_recognized_by_monitor_patterns() {
+        let mut manufacturer_data = HashMap::new();
+        let mut service_data = HashMap::new();
+        assert!(!has_sensor_data(&manufacturer_data, &service_data));
+
+        manufacturer_data.insert(0x004C, vec![0x02, 0x15]);
+        service_data.insert(Uuid::from_u16(0xFE9F), vec![0x00]);
+        assert!(!has_sensor_data(&manufacturer_data, &service_data));
+
+        manufacturer_data.insert(RUUVI_COMPANY_ID, vec![0x05]);
+        assert!(has_sensor_data(&manufacturer_data, &HashMap::new()));
+        service_data.insert(bthome_uuid(), vec![0x40]);
+        assert!(has_sensor_data(&HashMap::new(), &service_data));
+        let xiaomi = HashMap::from([(environmental_sensing_uuid(), vec![0x00])]);
+        assert!(has_sensor_data(&HashMap::new(), &xiaomi));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_maximum_and_resets() {
+        let mut backoff = Backoff::default();
+
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(vec![1, 2, 4, 8, 16, 30, 30], delays);
+
+        backoff.reset();
+        assert_eq!(INITIAL_BACKOFF, backoff.next_delay());
+    }
+
     #[test]
     fn device_addresses_are_formatted_lowercase() {
         let addr = bluer::Address([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
@@ -242,12 +1119,80 @@ mod tests {
     }
 
     #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
     async fn mark_active_allows_first_seen_only_once() {
-        let active = Arc::new(Mutex::new(HashSet::new()));
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let active = ActiveDevices::new("hci7", Metrics::register());
+
+        assert!(active.mark_active("aa:bb").await);
+        assert!(!active.mark_active("aa:bb").await);
+        assert!(active.mark_active("cc:dd").await);
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(2.0),
+            gauge_value(&snapshot, "ruuvi_tracked_devices", &[("adapter", "hci7")])
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn lost_devices_are_cancelled_and_untracked() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let active = ActiveDevices::new("hci8", Metrics::register());
+        let task = tokio::spawn(std::future::pending::<()>());
+
+        assert!(active.mark_active("aa:bb").await);
+        active.set_task("aa:bb", task.abort_handle()).await;
+        active.cancel("aa:bb").await;
+
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert!(active.mark_active("aa:bb").await);
+        active.cancel("aa:bb").await;
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(0.0),
+            gauge_value(&snapshot, "ruuvi_tracked_devices", &[("adapter", "hci8")])
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn closing_aborts_tasks_and_ignores_late_removals() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let previous = ActiveDevices::new("hci9", metrics.clone());
+        let task = tokio::spawn(std::future::pending::<()>());
+        assert!(previous.mark_active("aa:bb").await);
+        assert!(previous.mark_active("cc:dd").await);
+        previous.set_task("aa:bb", task.abort_handle()).await;
 
-        assert!(mark_active(&active, "aa:bb").await);
-        assert!(!mark_active(&active, "aa:bb").await);
-        assert!(mark_active(&active, "cc:dd").await);
+        previous.close().await;
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(
+            Some(0.0),
+            gauge_value(
+                &take_snapshot(),
+                "ruuvi_tracked_devices",
+                &[("adapter", "hci9")]
+            )
+        );
+
+        let current = ActiveDevices::new("hci9", metrics);
+        assert!(current.mark_active("aa:bb").await);
+        // a task of the previous connection finishing late
+        previous.remove("cc:dd").await;
+        assert_eq!(
+            Some(1.0),
+            gauge_value(
+                &take_snapshot(),
+                "ruuvi_tracked_devices",
+                &[("adapter", "hci9")]
+            )
+        );
     }
 
     #[test]
@@ -259,12 +1204,13 @@ mod tests {
         let payload = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
         map.insert(0x0499, payload.to_vec());
 
-        handle_device_property(
-            &metrics,
+        let advertisement = advertisement_from_property(
             "aa:bb",
             DeviceEvent::PropertyChanged(ManufacturerData(map)),
             None,
-        );
+        )
+        .expect("advertisement");
+        handle_advertisement(&metrics, &mut Decoder::default(), &advertisement);
 
         let snapshot = take_snapshot();
         assert_eq!(
@@ -285,12 +1231,13 @@ mod tests {
         let mut map = std::collections::HashMap::new();
         map.insert(0x1234, vec![0xde, 0xad, 0xbe, 0xef]);
 
-        handle_device_property(
-            &metrics,
+        let advertisement = advertisement_from_property(
             "aa:bb",
             DeviceEvent::PropertyChanged(ManufacturerData(map)),
             None,
-        );
+        )
+        .expect("advertisement");
+        handle_advertisement(&metrics, &mut Decoder::default(), &advertisement);
 
         let snapshot = take_snapshot();
         let value = counter_value(
@@ -308,12 +1255,10 @@ mod tests {
         clear();
         let metrics = Metrics::register();
 
-        handle_device_property(
-            &metrics,
-            "aa:bb",
-            DeviceEvent::PropertyChanged(Rssi(-42)),
-            None,
-        );
+        let advertisement =
+            advertisement_from_property("aa:bb", DeviceEvent::PropertyChanged(Rssi(-42)), None)
+                .expect("advertisement");
+        handle_advertisement(&metrics, &mut Decoder::default(), &advertisement);
 
         let snapshot = take_snapshot();
         assert!(
@@ -324,48 +1269,43 @@ mod tests {
 
     #[test]
     fn advertising_flags_are_ignored() {
-        let _guard = crate::test_utils::metrics::guard();
-        clear();
-        let metrics = Metrics::register();
-
-        handle_device_property(
-            &metrics,
+        let advertisement = advertisement_from_property(
             "aa:bb",
             DeviceEvent::PropertyChanged(AdvertisingFlags(vec![0x01, 0x02])),
             None,
         );
 
-        let snapshot = take_snapshot();
-        let value = counter_value(
-            &snapshot,
-            "ruuvi_frames_total",
-            &[("device", "aa:bb"), ("format", "5")],
-        )
-        .unwrap_or(0);
-        assert_eq!(0, value);
+        assert_eq!(None, advertisement);
     }
 
     #[test]
     fn unrelated_properties_fall_through() {
-        let _guard = crate::test_utils::metrics::guard();
-        clear();
-        let metrics = Metrics::register();
-
-        handle_device_property(
-            &metrics,
+        let advertisement = advertisement_from_property(
             "aa:bb",
             DeviceEvent::PropertyChanged(bluer::DeviceProperty::Name("demo".into())),
             None,
         );
 
-        let snapshot = take_snapshot();
-        let value = counter_value(
-            &snapshot,
-            "ruuvi_frames_total",
-            &[("device", "aa:bb"), ("format", "5")],
+        assert_eq!(None, advertisement);
+    }
+
+    #[test]
+    fn service_data_is_forwarded() {
+        let uuid = bluer::Uuid::from_u128(0x0000fcd2_0000_1000_8000_00805f9b34fb);
+        let advertisement = advertisement_from_property(
+            "aa:bb",
+            DeviceEvent::PropertyChanged(ServiceData(
+                std::iter::once((uuid, vec![0x40, 0x01])).collect(),
+            )),
+            None,
         )
-        .unwrap_or(0);
-        assert_eq!(0, value);
+        .expect("advertisement");
+
+        assert_eq!("aa:bb", advertisement.address);
+        assert_eq!(
+            Some(&vec![0x40, 0x01]),
+            advertisement.service_data.get(&uuid)
+        );
     }
 
     #[tokio::test]
@@ -410,7 +1350,8 @@ mod tests {
         let _guard = crate::test_utils::metrics::guard();
         clear();
         let metrics = Metrics::register();
-        let active = Arc::new(Mutex::new(HashSet::from(["aa:bb".to_string()])));
+        let active = Arc::new(ActiveDevices::new("hci0", metrics.clone()));
+        assert!(active.mark_active("aa:bb").await);
         let payload = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
 
         let mut events = stream::iter(vec![
@@ -420,14 +1361,23 @@ mod tests {
             )),
         ]);
 
-        process_events_stream(&mut events, metrics, "aa:bb", active.clone()).await;
+        let (sink, mut advertisements) = tokio::sync::mpsc::channel(8);
+        process_events_stream(&mut events, sink, "hci0", "aa:bb", active.clone()).await;
 
-        assert!(!active.lock().await.contains("aa:bb"));
+        assert!(active.mark_active("aa:bb").await);
+        while let Some(advertisement) = advertisements.recv().await {
+            assert_eq!(Some("hci0"), advertisement.adapter.as_deref());
+            handle_advertisement(&metrics, &mut Decoder::default(), &advertisement);
+        }
 
         let snapshot = take_snapshot();
         assert!(
-            gauge_value(&snapshot, "ruuvi_rssi_dbm", &[("device", "aa:bb")])
-                .is_some_and(|v| (v + 20.0).abs() < f64::EPSILON)
+            gauge_value(
+                &snapshot,
+                "ruuvi_rssi_dbm",
+                &[("device", "aa:bb"), ("adapter", "hci0")]
+            )
+            .is_some_and(|v| (v + 20.0).abs() < f64::EPSILON)
         );
         assert_eq!(
             Some(1),
@@ -446,16 +1396,17 @@ mod tests {
         let metrics = Metrics::register();
         let payload = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
 
-        seed_from_properties_iter(
+        let advertisement = seed_from_properties_iter(
             vec![
                 ManufacturerData(std::iter::once((0x1234, vec![0x01])).collect()),
                 ManufacturerData(std::iter::once((0x0499, payload.to_vec())).collect()),
                 ManufacturerData(std::iter::once((0x0499, vec![0x00])).collect()),
             ],
-            &metrics,
             "aa:bb",
             None,
-        );
+        )
+        .expect("advertisement");
+        handle_advertisement(&metrics, &mut Decoder::default(), &advertisement);
 
         let snapshot = take_snapshot();
         // Only the first manufacturer data (non-Ruuvi) is processed, so no frames counted.
@@ -0,0 +1,262 @@
+//! Decoder for unencrypted [BTHome v2](https://bthome.io/format/) service data.
+
+use bluer::{Uuid, UuidExt};
+
+use crate::calibration::Calibration;
+use crate::metrics::Metrics;
+
+pub(crate) const BTHOME_SERVICE_UUID16: u16 = 0xFCD2;
+pub(crate) const VENDOR_BTHOME: &str = "bthome";
+
+const DEVICE_INFO_ENCRYPTED: u8 = 0b0000_0001;
+const DEVICE_INFO_VERSION_SHIFT: u8 = 5;
+const BTHOME_VERSION: u8 = 2;
+/// Objects whose value is prefixed with its length in bytes.
+const OBJECT_TEXT: u8 = 0x53;
+const OBJECT_RAW: u8 = 0x54;
+
+pub(crate) fn bthome_uuid() -> Uuid {
+    Uuid::from_u16(BTHOME_SERVICE_UUID16)
+}
+
+/// Readings of a single BTHome advertisement, objects not sent by the
+/// sensor are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct BthomeData {
+    pub packet_id: Option<u8>,
+    /// Battery level in %
+    pub battery: Option<f64>,
+    /// Temperature in Celsius
+    pub temperature: Option<f64>,
+    /// Relative humidity in %
+    pub humidity: Option<f64>,
+    /// Pressure in hPa
+    pub pressure: Option<f64>,
+    /// Illuminance in lux
+    pub illuminance: Option<f64>,
+    /// Dew point in Celsius
+    pub dew_point: Option<f64>,
+    /// Battery voltage in V
+    pub voltage: Option<f64>,
+    /// PM2.5 in ug/m3
+    pub pm2_5: Option<f64>,
+    /// PM10 in ug/m3
+    pub pm10_0: Option<f64>,
+    /// CO2 in ppm
+    pub co2: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BthomeError {
+    Empty,
+    Encrypted,
+    UnsupportedVersion(u8),
+    Truncated(u8),
+}
+
+impl std::fmt::Display for BthomeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BthomeError::Empty => write!(f, "empty service data"),
+            BthomeError::Encrypted => write!(f, "encrypted BTHome data is not supported"),
+            BthomeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported BTHome version {}", version)
+            }
+            BthomeError::Truncated(id) => write!(f, "truncated BTHome object 0x{:02X}", id),
+        }
+    }
+}
+
+impl BthomeData {
+    pub(crate) fn calibrated(self, calibration: &Calibration) -> Self {
+        Self {
+            temperature: self.temperature.map(|v| calibration.temperature.apply(v)),
+            humidity: self
+                .humidity
+                .map(|v| calibration.humidity.apply_humidity(v)),
+            pressure: self.pressure.map(|v| calibration.pressure.apply(v)),
+            co2: self.co2.map(|v| calibration.co2.apply(v)),
+            ..self
+        }
+    }
+}
+
+/// Size in bytes of the value of each fixed size object id. Objects are not
+/// self-delimiting, so parsing has to stop at the first unknown id.
+fn object_size(id: u8) -> Option<usize> {
+    match id {
+        0x00 | 0x01 | 0x09 | 0x0F..=0x11 | 0x15..=0x2F | 0x3A | 0x46 => Some(1),
+        0x02 | 0x03 | 0x06 | 0x07 | 0x08 | 0x0C | 0x0D | 0x0E | 0x12 | 0x13 | 0x14 => Some(2),
+        0x3C | 0x3D | 0x3F | 0x40 | 0x41 | 0x43 | 0x44 | 0x45 | 0x47 | 0x48 | 0x49 | 0x4A => {
+            Some(2)
+        }
+        0x51 | 0x52 => Some(2),
+        0x04 | 0x05 | 0x0A | 0x0B | 0x42 | 0x4B => Some(3),
+        0x3E | 0x4C | 0x4D | 0x4E | 0x4F | 0x50 => Some(4),
+        // device type id, firmware version as 4 and 3 bytes
+        0xF0 => Some(2),
+        0xF1 => Some(4),
+        0xF2 => Some(3),
+        _ => None,
+    }
+}
+
+fn unsigned(bytes: &[u8]) -> f64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u32, |acc, b| (acc << 8) | u32::from(*b))
+        .into()
+}
+
+fn signed16(bytes: &[u8]) -> f64 {
+    f64::from(i16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Objects after the first unknown id are skipped, the ones before it are
+/// still returned.
+pub(crate) fn decode(value: &[u8]) -> Result<BthomeData, BthomeError> {
+    let (&device_info, mut objects) = value.split_first().ok_or(BthomeError::Empty)?;
+    if device_info & DEVICE_INFO_ENCRYPTED != 0 {
+        return Err(BthomeError::Encrypted);
+    }
+    let version = device_info >> DEVICE_INFO_VERSION_SHIFT;
+    if version != BTHOME_VERSION {
+        return Err(BthomeError::UnsupportedVersion(version));
+    }
+
+    let mut data = BthomeData::default();
+    while let Some((&id, rest)) = objects.split_first() {
+        let size = match id {
+            OBJECT_TEXT | OBJECT_RAW => match rest.first() {
+                Some(&length) => 1 + usize::from(length),
+                None => return Err(BthomeError::Truncated(id)),
+            },
+            _ => match object_size(id) {
+                Some(size) => size,
+                None => break,
+            },
+        };
+        if rest.len() < size {
+            return Err(BthomeError::Truncated(id));
+        }
+        let (bytes, next) = rest.split_at(size);
+        match id {
+            0x00 => data.packet_id = Some(bytes[0]),
+            0x01 => data.battery = Some(f64::from(bytes[0])),
+            0x02 => data.temperature = Some(signed16(bytes) * 0.01),
+            0x45 => data.temperature = Some(signed16(bytes) * 0.1),
+            0x03 => data.humidity = Some(unsigned(bytes) * 0.01),
+            0x2E => data.humidity = Some(unsigned(bytes)),
+            0x04 => data.pressure = Some(unsigned(bytes) * 0.01),
+            0x05 => data.illuminance = Some(unsigned(bytes) * 0.01),
+            0x08 => data.dew_point = Some(signed16(bytes) * 0.01),
+            0x0C => data.voltage = Some(unsigned(bytes) * 0.001),
+            0x0D => data.pm2_5 = Some(unsigned(bytes)),
+            0x0E => data.pm10_0 = Some(unsigned(bytes)),
+            0x12 => data.co2 = Some(unsigned(bytes)),
+            _ => {}
+        }
+        objects = next;
+    }
+    Ok(data)
+}
+
+pub(crate) fn apply_bthome_metrics(metrics: &Metrics, addr: &str, data: &BthomeData) {
+    if let Some(packet_id) = data.packet_id {
+        metrics.set_seqno(addr, f64::from(packet_id));
+    }
+    if let Some(battery) = data.battery {
+        metrics.set_battery_percent(addr, battery);
+    }
+    if let Some(temperature) = data.temperature {
+        metrics.set_temperature(addr, temperature);
+    }
+    if let Some(humidity) = data.humidity {
+        metrics.set_humidity(addr, humidity / 100.0);
+    }
+    if let Some(pressure) = data.pressure {
+        metrics.set_pressure(addr, pressure);
+    }
+    if let Some(illuminance) = data.illuminance {
+        metrics.set_illuminance(addr, illuminance);
+    }
+    if let Some(dew_point) = data.dew_point {
+        metrics.set_dew_point(addr, dew_point);
+    }
+    if let Some(voltage) = data.voltage {
+        metrics.set_voltage(addr, voltage);
+    }
+    if let Some(pm2_5) = data.pm2_5 {
+        metrics.set_pm2_5(addr, pm2_5);
+    }
+    if let Some(pm10_0) = data.pm10_0 {
+        metrics.set_pm10_0(addr, pm10_0);
+    }
+    if let Some(co2) = data.co2 {
+        metrics.set_co2(addr, co2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bthome_objects_are_decoded() {
+        // packet id 9, battery 97%, temperature 23.45°C, humidity 55.23%,
+        // pressure 1008.83 hPa, illuminance 13460.67 lx, voltage 3.074 V, CO2 1250 ppm
+        let payload = hex_literal::hex!("400009016102290903931504138A0105138A140C020C12E204");
+
+        let data = decode(&payload).expect("decode BTHome");
+
+        assert_eq!(Some(9), data.packet_id);
+        assert_eq!(Some(97.0), data.battery);
+        assert!((data.temperature.unwrap() - 23.45).abs() < 1e-9);
+        assert!((data.humidity.unwrap() - 55.23).abs() < 1e-9);
+        assert!((data.pressure.unwrap() - 1008.83).abs() < 1e-9);
+        assert!((data.illuminance.unwrap() - 13460.67).abs() < 1e-9);
+        assert!((data.voltage.unwrap() - 3.074).abs() < 1e-9);
+        assert_eq!(Some(1250.0), data.co2);
+        assert_eq!(None, data.pm2_5);
+    }
+
+    #[test]
+    fn unsupported_bthome_data_is_rejected() {
+        assert_eq!(Err(BthomeError::Empty), decode(&[]));
+        assert_eq!(Err(BthomeError::Encrypted), decode(&[0x41, 0x01, 0x61]));
+        assert_eq!(
+            Err(BthomeError::UnsupportedVersion(1)),
+            decode(&[0x20, 0x01, 0x61])
+        );
+        assert_eq!(
+            Err(BthomeError::Truncated(0x02)),
+            decode(&[0x40, 0x02, 0x29])
+        );
+        assert_eq!(Err(BthomeError::Truncated(0x53)), decode(&[0x40, 0x53]));
+    }
+
+    #[test]
+    fn objects_before_an_unknown_id_are_kept() {
+        // packet id 3, temperature 23.45°C, unknown object 0xEE, battery 97%
+        let payload = hex_literal::hex!("400003022909EE0161");
+
+        let data = decode(&payload).expect("decode BTHome");
+
+        assert_eq!(Some(3), data.packet_id);
+        assert!((data.temperature.unwrap() - 23.45).abs() < 1e-9);
+        assert_eq!(None, data.battery);
+    }
+
+    #[test]
+    fn device_info_and_length_prefixed_objects_are_skipped() {
+        // temperature 23.45°C, text "abc", raw 0x01 0x02, device type 0x0001,
+        // firmware 1.2.3.4 and 1.2.3, humidity 55.23%
+        let payload = hex_literal::hex!("40022909530361626354020102F00100F104030201F2030201039315");
+
+        let data = decode(&payload).expect("decode BTHome");
+
+        assert!((data.temperature.unwrap() - 23.45).abs() < 1e-9);
+        assert!((data.humidity.unwrap() - 55.23).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,139 @@
+//! Per-device linear corrections of the sensor readings.
+
+use serde::Deserialize;
+
+use crate::ruuvi::{AirQualityReadings, EnvironmentReadings};
+
+/// `value * gain + offset`, in the unit of the exported metric
+/// (°C, %RH, hPa, ppm).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Correction {
+    pub offset: f64,
+    pub gain: f64,
+}
+
+impl Correction {
+    pub const IDENTITY: Correction = Correction {
+        offset: 0.0,
+        gain: 1.0,
+    };
+
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.gain + self.offset
+    }
+
+    /// Corrects a relative humidity in %RH and clamps the corrected value to 0..=100%.
+    /// Uncorrected readings are passed through as reported, even when out of range.
+    pub fn apply_humidity(&self, value: f64) -> f64 {
+        if *self == Self::IDENTITY {
+            value
+        } else {
+            self.apply(value).clamp(0.0, 100.0)
+        }
+    }
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    pub temperature: Correction,
+    pub humidity: Correction,
+    pub pressure: Correction,
+    pub co2: Correction,
+}
+
+impl Calibration {
+    pub const IDENTITY: Calibration = Calibration {
+        temperature: Correction::IDENTITY,
+        humidity: Correction::IDENTITY,
+        pressure: Correction::IDENTITY,
+        co2: Correction::IDENTITY,
+    };
+
+    /// Humidity is corrected in %RH, see [`Correction::apply_humidity`].
+    pub(crate) fn environment(&self, env: EnvironmentReadings) -> EnvironmentReadings {
+        EnvironmentReadings {
+            temperature: self.temperature.apply(env.temperature),
+            humidity_ratio: self.humidity.apply_humidity(env.humidity_ratio * 100.0) / 100.0,
+            pressure_hpa: self.pressure.apply(env.pressure_hpa),
+        }
+    }
+
+    pub(crate) fn air_quality(&self, air: AirQualityReadings) -> AirQualityReadings {
+        AirQualityReadings {
+            co2: air.co2.map(|co2| self.co2.apply(co2)),
+            ..air
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrections_are_applied_in_metric_units() {
+        let calibration = Calibration {
+            temperature: Correction {
+                offset: -0.4,
+                gain: 1.0,
+            },
+            humidity: Correction {
+                offset: 3.0,
+                gain: 1.0,
+            },
+            pressure: Correction {
+                offset: 0.0,
+                gain: 1.01,
+            },
+            co2: Correction::IDENTITY,
+        };
+
+        let env = calibration.environment(EnvironmentReadings {
+            temperature: 21.4,
+            humidity_ratio: 0.45,
+            pressure_hpa: 1000.0,
+        });
+
+        assert!((env.temperature - 21.0).abs() < 1e-9);
+        assert!((env.humidity_ratio - 0.48).abs() < 1e-9);
+        assert!((env.pressure_hpa - 1010.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn corrected_humidity_is_clamped() {
+        let calibration = Calibration {
+            humidity: Correction {
+                offset: 5.0,
+                gain: 1.0,
+            },
+            ..Default::default()
+        };
+
+        let env = calibration.environment(EnvironmentReadings {
+            temperature: 20.0,
+            humidity_ratio: 0.98,
+            pressure_hpa: 1000.0,
+        });
+
+        assert!((env.humidity_ratio - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn uncalibrated_humidity_is_not_clamped() {
+        let env = Calibration::IDENTITY.environment(EnvironmentReadings {
+            temperature: 20.0,
+            humidity_ratio: 1.05,
+            pressure_hpa: 1000.0,
+        });
+
+        assert!((env.humidity_ratio - 1.05).abs() < 1e-9);
+    }
+}
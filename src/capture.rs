@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::source::Advertisement;
+
+/// One received manufacturer data payload, stored as a single JSONL line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CaptureRecord {
+    /// Receive time in seconds since the unix epoch.
+    pub timestamp: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<String>,
+    pub address: String,
+    pub rssi: Option<i16>,
+    pub company_id: u16,
+    pub data: String,
+}
+
+pub(crate) fn encode_hex(value: &[u8]) -> String {
+    value.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+fn open_append(path: &Path) -> io::Result<(LineWriter<File>, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let written = file.metadata()?.len();
+    Ok((LineWriter::new(file), written))
+}
+
+/// Appends received advertisements to a JSONL file. Once the file exceeds
+/// `max_bytes` it is rotated to `<path>.1`, keeping at most `max_files` old files.
+pub(crate) struct CaptureWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    writer: LineWriter<File>,
+    written: u64,
+    last_rssi: HashMap<String, i16>,
+}
+
+impl CaptureWriter {
+    pub(crate) fn open(
+        path: impl Into<PathBuf>,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let (writer, written) = open_append(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            writer,
+            written,
+            last_rssi: HashMap::new(),
+        })
+    }
+
+    /// BlueZ reports RSSI separately from the data, so the last known RSSI of
+    /// the device is recorded alongside each payload.
+    pub(crate) fn record(&mut self, advertisement: &Advertisement) -> io::Result<()> {
+        if let Some(rssi) = advertisement.rssi {
+            self.last_rssi.insert(advertisement.address.clone(), rssi);
+        }
+        let timestamp = advertisement
+            .received_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        for (company_id, value) in &advertisement.manufacturer_data {
+            let record = CaptureRecord {
+                timestamp,
+                adapter: advertisement.adapter.clone(),
+                gateway: advertisement.gateway.clone(),
+                address: advertisement.address.clone(),
+                rssi: self.last_rssi.get(&advertisement.address).copied(),
+                company_id: *company_id,
+                data: encode_hex(value),
+            };
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+                self.rotate()?;
+            }
+            self.writer.write_all(&line)?;
+            self.written += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        (self.writer, self.written) = open_append(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::RUUVI_COMPANY_ID;
+    use std::time::Duration;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ruuvi-prometheus-rs-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn advertisement(rssi: Option<i16>) -> Advertisement {
+        let mut advertisement = Advertisement::new("aa:bb");
+        advertisement.adapter = Some("hci0".to_string());
+        advertisement.rssi = rssi;
+        advertisement.received_at =
+            SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        advertisement
+            .manufacturer_data
+            .insert(RUUVI_COMPANY_ID, vec![0x05, 0x12, 0xfc]);
+        advertisement
+    }
+
+    #[test]
+    fn records_are_written_as_jsonl() {
+        let dir = temp_dir("capture-jsonl");
+        let path = dir.join("capture.jsonl");
+        let mut writer = CaptureWriter::open(&path, 1024 * 1024, 2).unwrap();
+
+        let mut rssi_only = Advertisement::new("aa:bb");
+        rssi_only.rssi = Some(-48);
+        writer.record(&rssi_only).unwrap();
+        writer.record(&advertisement(None)).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let records: Vec<CaptureRecord> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            vec![CaptureRecord {
+                timestamp: 1_700_000_000.5,
+                adapter: Some("hci0".to_string()),
+                gateway: None,
+                address: "aa:bb".to_string(),
+                rssi: Some(-48),
+                company_id: RUUVI_COMPANY_ID,
+                data: "0512fc".to_string(),
+            }],
+            records
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn files_are_rotated_when_full() {
+        let dir = temp_dir("capture-rotate");
+        let path = dir.join("capture.jsonl");
+        let mut writer = CaptureWriter::open(&path, 100, 2).unwrap();
+
+        for _ in 0..5 {
+            writer.record(&advertisement(Some(-60))).unwrap();
+        }
+
+        assert_eq!(1, fs::read_to_string(&path).unwrap().lines().count());
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,16 +1,138 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use duration_string::DurationString;
+use serde::Deserialize;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use crate::battery::{BatteryModel, DEFAULT_FULL_VOLTS, DEFAULT_LOW_PERCENT};
+use crate::bluetooth::{AdapterSelection, MonitorRssi, ScanMode, parse_sampling_period};
+use crate::calibration::Calibration;
+use crate::filter::{DeviceFilter, parse_patterns};
+use crate::formats::EncryptionKey;
+use crate::liveness::{DEFAULT_DOWN_AFTER, DEFAULT_RETENTION};
+use crate::orientation::DEFAULT_ORIENTATION_THRESHOLD_DEGREES;
+use crate::psychrometrics::DerivedMetrics;
+use crate::source::{decode_hex, normalize_device_address};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     pub binding: SocketAddr,
     pub idle_timeout: Duration,
     pub enable_process_collection: bool,
     pub process_collection_interval: Duration,
-    pub adapter_name: String,
+    pub adapters: AdapterSelection,
+    pub scan_mode: ScanMode,
+    pub monitor_rssi: MonitorRssi,
+    pub enable_bluetooth: bool,
+    pub gateway_binding: Option<SocketAddr>,
+    pub mqtt: Option<MqttConfig>,
+    pub capture: Option<CaptureConfig>,
+    pub replay: Option<ReplayConfig>,
+    pub encryption_keys: BTreeMap<String, EncryptionKey>,
+    pub devices: BTreeMap<String, DeviceConfig>,
+    pub device_filter: DeviceFilter,
+    pub derived_metrics: DerivedMetrics,
+    pub altitude: Option<f64>,
+    pub orientation_threshold_degrees: f64,
+    pub battery: BatteryModel,
+    pub device_down_after: Duration,
+    pub device_retention: Duration,
+}
+
+/// An entry of the device registry, keyed by MAC address.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct DeviceConfig {
+    #[serde(flatten)]
+    pub alias: DeviceAlias,
+    #[serde(default)]
+    pub calibration: Calibration,
+    /// Altitude in m, overriding the global `ALTITUDE`
+    pub altitude: Option<f64>,
+}
+
+/// Friendly labels for a device, attached to all of its metrics.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize)]
+pub struct DeviceAlias {
+    pub name: Option<String>,
+    pub location: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CaptureConfig {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplayConfig {
+    pub path: PathBuf,
+    pub realtime: bool,
+}
+
+/// Parses `<mac>=<32 hex digits>` pairs separated by commas.
+fn parse_encryption_keys(value: &str) -> BTreeMap<String, EncryptionKey> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (mac, key) = entry
+                .split_once('=')
+                .unwrap_or_else(|| panic!("invalid ENCRYPTION_KEYS entry: {}", entry));
+            let key: EncryptionKey = decode_hex(key)
+                .and_then(|key| key.try_into().ok())
+                .unwrap_or_else(|| panic!("invalid encryption key for {}", mac));
+            (normalize_device_address(mac.trim()), key)
+        })
+        .collect()
+}
+
+/// Labels set by the exporter itself, which extra device labels must not shadow.
+const RESERVED_LABELS: [&str; 10] = [
+    "device", "name", "location", "vendor", "axis", "format", "kind", "gateway", "reason",
+    "adapter",
+];
+
+/// Reads a JSON object mapping MAC addresses to their `DeviceConfig`.
+fn load_devices(path: &str) -> BTreeMap<String, DeviceConfig> {
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", path, err));
+    parse_devices(&content)
+}
+
+fn parse_devices(content: &str) -> BTreeMap<String, DeviceConfig> {
+    let devices: BTreeMap<String, DeviceConfig> =
+        serde_json::from_str(content).expect("invalid device registry");
+    devices
+        .into_iter()
+        .map(|(mac, device)| {
+            if let Some(label) = device
+                .alias
+                .labels
+                .keys()
+                .find(|label| RESERVED_LABELS.contains(&label.as_str()))
+            {
+                panic!("label {} of device {} is reserved", label, mac);
+            }
+            (normalize_device_address(&mac), device)
+        })
+        .collect()
 }
 
 impl Config {
@@ -31,20 +153,149 @@ impl Config {
             .parse::<DurationString>()
             .unwrap()
             .into();
-        let adapter_name = env::var("ADAPTER_NAME").unwrap_or("hci0".to_string());
+        let adapters =
+            AdapterSelection::parse(&env::var("ADAPTER_NAME").unwrap_or("hci0".to_string()));
+        let scan_mode = ScanMode::parse(&env::var("SCAN_MODE").unwrap_or("auto".to_string()));
+        let monitor_rssi = MonitorRssi {
+            low_threshold: env::var("MONITOR_RSSI_LOW_THRESHOLD")
+                .ok()
+                .map(|dbm| dbm.parse::<i16>().unwrap()),
+            high_threshold: env::var("MONITOR_RSSI_HIGH_THRESHOLD")
+                .ok()
+                .map(|dbm| dbm.parse::<i16>().unwrap()),
+            low_timeout: env::var("MONITOR_RSSI_LOW_TIMEOUT")
+                .ok()
+                .map(|duration| duration.parse::<DurationString>().unwrap().into()),
+            high_timeout: env::var("MONITOR_RSSI_HIGH_TIMEOUT")
+                .ok()
+                .map(|duration| duration.parse::<DurationString>().unwrap().into()),
+            sampling_period: parse_sampling_period(
+                &env::var("MONITOR_RSSI_SAMPLING_PERIOD").unwrap_or("first".to_string()),
+            ),
+        };
+        monitor_rssi.validate();
+        let enable_bluetooth = env::var("ENABLE_BLUETOOTH")
+            .unwrap_or("true".to_string())
+            .parse::<bool>()
+            .unwrap();
+        let gateway_binding: Option<SocketAddr> = env::var("GATEWAY_PORT")
+            .ok()
+            .map(|port| format!("0.0.0.0:{}", port).parse().unwrap());
+        let mqtt = env::var("MQTT_HOST").ok().map(|host| MqttConfig {
+            host,
+            port: env::var("MQTT_PORT")
+                .unwrap_or("1883".to_string())
+                .parse::<u16>()
+                .unwrap(),
+            topic: env::var("MQTT_TOPIC").unwrap_or("ruuvi/#".to_string()),
+            client_id: env::var("MQTT_CLIENT_ID").unwrap_or("ruuvi-prometheus-rs".to_string()),
+            username: env::var("MQTT_USERNAME").ok(),
+            password: env::var("MQTT_PASSWORD").ok(),
+        });
+        let capture = env::var("CAPTURE_FILE").ok().map(|path| CaptureConfig {
+            path: PathBuf::from(path),
+            max_bytes: env::var("CAPTURE_MAX_BYTES")
+                .unwrap_or("10485760".to_string())
+                .parse::<u64>()
+                .unwrap(),
+            max_files: env::var("CAPTURE_MAX_FILES")
+                .unwrap_or("5".to_string())
+                .parse::<usize>()
+                .unwrap(),
+        });
+        let replay = env::var("REPLAY_FILE").ok().map(|path| ReplayConfig {
+            path: PathBuf::from(path),
+            realtime: env::var("REPLAY_REALTIME")
+                .unwrap_or("true".to_string())
+                .parse::<bool>()
+                .unwrap(),
+        });
+        let encryption_keys = env::var("ENCRYPTION_KEYS")
+            .map(|keys| parse_encryption_keys(&keys))
+            .unwrap_or_default();
+        let devices = env::var("DEVICES_FILE")
+            .map(|path| load_devices(&path))
+            .unwrap_or_default();
+        let device_filter = DeviceFilter {
+            allow: parse_patterns(&env::var("DEVICE_ALLOWLIST").unwrap_or_default()),
+            deny: parse_patterns(&env::var("DEVICE_DENYLIST").unwrap_or_default()),
+        };
+        let derived_metrics =
+            DerivedMetrics::parse(&env::var("DERIVED_METRICS").unwrap_or_default());
+        let altitude = env::var("ALTITUDE")
+            .ok()
+            .map(|altitude| altitude.parse::<f64>().unwrap());
+        let orientation_threshold_degrees = env::var("ORIENTATION_THRESHOLD")
+            .map(|degrees| degrees.parse::<f64>().unwrap())
+            .unwrap_or(DEFAULT_ORIENTATION_THRESHOLD_DEGREES);
+        let battery = BatteryModel {
+            full_volts: env::var("BATTERY_FULL_VOLTS")
+                .map(|volts| volts.parse::<f64>().unwrap())
+                .unwrap_or(DEFAULT_FULL_VOLTS),
+            low_percent: env::var("BATTERY_LOW_PERCENT")
+                .map(|percent| percent.parse::<f64>().unwrap())
+                .unwrap_or(DEFAULT_LOW_PERCENT),
+        };
+        battery.validate();
+        let device_down_after: Duration = env::var("DEVICE_DOWN_AFTER")
+            .map(|duration| duration.parse::<DurationString>().unwrap().into())
+            .unwrap_or(DEFAULT_DOWN_AFTER);
+        let device_retention: Duration = env::var("DEVICE_RETENTION")
+            .map(|duration| duration.parse::<DurationString>().unwrap().into())
+            .unwrap_or(DEFAULT_RETENTION);
         Self {
             binding,
             idle_timeout,
             enable_process_collection,
             process_collection_interval,
-            adapter_name,
+            adapters,
+            scan_mode,
+            monitor_rssi,
+            enable_bluetooth,
+            gateway_binding,
+            mqtt,
+            capture,
+            replay,
+            encryption_keys,
+            devices,
+            device_filter,
+            derived_metrics,
+            altitude,
+            orientation_threshold_degrees,
+            battery,
+            device_down_after,
+            device_retention,
         }
     }
+
+    pub fn device_aliases(&self) -> BTreeMap<String, DeviceAlias> {
+        self.devices
+            .iter()
+            .map(|(mac, device)| (mac.clone(), device.alias.clone()))
+            .collect()
+    }
+
+    pub fn calibrations(&self) -> BTreeMap<String, Calibration> {
+        self.devices
+            .iter()
+            .map(|(mac, device)| (mac.clone(), device.calibration))
+            .collect()
+    }
+
+    pub fn altitudes(&self) -> BTreeMap<String, f64> {
+        self.devices
+            .iter()
+            .filter_map(|(mac, device)| Some((mac.clone(), device.altitude?)))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::calibration::Correction;
+    use crate::filter::AddressPattern;
+    use bluer::monitor::RssiSamplingPeriod;
     use std::sync::Mutex;
 
     static ENV_LOCK: Mutex<()> = Mutex::new(());
@@ -82,6 +333,28 @@ mod tests {
                 ("ENABLE_PROCESS_COLLECTION", None),
                 ("PROCESS_COLLECTION_INTERVAL", None),
                 ("ADAPTER_NAME", None),
+                ("SCAN_MODE", None),
+                ("MONITOR_RSSI_LOW_THRESHOLD", None),
+                ("MONITOR_RSSI_HIGH_THRESHOLD", None),
+                ("MONITOR_RSSI_LOW_TIMEOUT", None),
+                ("MONITOR_RSSI_HIGH_TIMEOUT", None),
+                ("MONITOR_RSSI_SAMPLING_PERIOD", None),
+                ("ENABLE_BLUETOOTH", None),
+                ("GATEWAY_PORT", None),
+                ("MQTT_HOST", None),
+                ("CAPTURE_FILE", None),
+                ("REPLAY_FILE", None),
+                ("ENCRYPTION_KEYS", None),
+                ("DEVICES_FILE", None),
+                ("DEVICE_ALLOWLIST", None),
+                ("DEVICE_DENYLIST", None),
+                ("DERIVED_METRICS", None),
+                ("ALTITUDE", None),
+                ("ORIENTATION_THRESHOLD", None),
+                ("BATTERY_FULL_VOLTS", None),
+                ("BATTERY_LOW_PERCENT", None),
+                ("DEVICE_DOWN_AFTER", None),
+                ("DEVICE_RETENTION", None),
             ],
             || {
                 let config = Config::from_env();
@@ -93,7 +366,26 @@ mod tests {
                 assert_eq!(Duration::from_secs(60), config.idle_timeout);
                 assert!(!config.enable_process_collection);
                 assert_eq!(Duration::from_secs(10), config.process_collection_interval);
-                assert_eq!("hci0", config.adapter_name);
+                assert_eq!(
+                    AdapterSelection::Named(vec!["hci0".to_string()]),
+                    config.adapters
+                );
+                assert_eq!(ScanMode::Auto, config.scan_mode);
+                assert_eq!(MonitorRssi::default(), config.monitor_rssi);
+                assert!(config.enable_bluetooth);
+                assert_eq!(None, config.gateway_binding);
+                assert_eq!(None, config.mqtt);
+                assert_eq!(None, config.capture);
+                assert_eq!(None, config.replay);
+                assert!(config.encryption_keys.is_empty());
+                assert!(config.devices.is_empty());
+                assert_eq!(DeviceFilter::default(), config.device_filter);
+                assert_eq!(DerivedMetrics::default(), config.derived_metrics);
+                assert_eq!(None, config.altitude);
+                assert_eq!(30.0, config.orientation_threshold_degrees);
+                assert_eq!(BatteryModel::default(), config.battery);
+                assert_eq!(Duration::from_secs(60), config.device_down_after);
+                assert_eq!(Duration::from_secs(86400), config.device_retention);
             },
         );
     }
@@ -106,7 +398,41 @@ mod tests {
                 ("IDLE_TIMEOUT", Some("120s")),
                 ("ENABLE_PROCESS_COLLECTION", Some("true")),
                 ("PROCESS_COLLECTION_INTERVAL", Some("30s")),
-                ("ADAPTER_NAME", Some("usb0")),
+                ("ADAPTER_NAME", Some("usb0,usb1")),
+                ("SCAN_MODE", Some("discovery")),
+                ("MONITOR_RSSI_LOW_THRESHOLD", Some("-95")),
+                ("MONITOR_RSSI_HIGH_THRESHOLD", Some("-80")),
+                ("MONITOR_RSSI_LOW_TIMEOUT", Some("1m")),
+                ("MONITOR_RSSI_HIGH_TIMEOUT", Some("3s")),
+                ("MONITOR_RSSI_SAMPLING_PERIOD", Some("10s")),
+                ("ENABLE_BLUETOOTH", Some("false")),
+                ("GATEWAY_PORT", Some("9186")),
+                ("MQTT_HOST", Some("broker.local")),
+                ("MQTT_PORT", Some("8883")),
+                ("MQTT_TOPIC", Some("ruuvi/+/+")),
+                ("MQTT_CLIENT_ID", None),
+                ("MQTT_USERNAME", Some("user")),
+                ("MQTT_PASSWORD", Some("secret")),
+                ("CAPTURE_FILE", Some("/tmp/capture.jsonl")),
+                ("CAPTURE_MAX_BYTES", Some("2048")),
+                ("CAPTURE_MAX_FILES", None),
+                ("REPLAY_FILE", Some("/tmp/replay.jsonl")),
+                ("REPLAY_REALTIME", Some("false")),
+                (
+                    "ENCRYPTION_KEYS",
+                    Some(
+                        "CB:B8:33:4C:88:4F=000102030405060708090a0b0c0d0e0f, aa:bb:cc:dd:ee:ff=ffffffffffffffffffffffffffffffff",
+                    ),
+                ),
+                ("DEVICE_ALLOWLIST", Some("cb:b8:*,aa:bb:cc:dd:ee:ff")),
+                ("DEVICE_DENYLIST", Some("CB:B8:33:4C:88:4F")),
+                ("DERIVED_METRICS", Some("absolute_humidity,humidex")),
+                ("ALTITUDE", Some("540")),
+                ("ORIENTATION_THRESHOLD", Some("15")),
+                ("BATTERY_FULL_VOLTS", Some("3.1")),
+                ("BATTERY_LOW_PERCENT", Some("25")),
+                ("DEVICE_DOWN_AFTER", Some("5m")),
+                ("DEVICE_RETENTION", Some("7d")),
             ],
             || {
                 let config = Config::from_env();
@@ -118,8 +444,156 @@ mod tests {
                 assert_eq!(Duration::from_secs(120), config.idle_timeout);
                 assert!(config.enable_process_collection);
                 assert_eq!(Duration::from_secs(30), config.process_collection_interval);
-                assert_eq!("usb0", config.adapter_name);
+                assert_eq!(
+                    AdapterSelection::Named(vec!["usb0".to_string(), "usb1".to_string()]),
+                    config.adapters
+                );
+                assert_eq!(ScanMode::Discovery, config.scan_mode);
+                assert_eq!(
+                    MonitorRssi {
+                        low_threshold: Some(-95),
+                        high_threshold: Some(-80),
+                        low_timeout: Some(Duration::from_secs(60)),
+                        high_timeout: Some(Duration::from_secs(3)),
+                        sampling_period: RssiSamplingPeriod::Period(Duration::from_secs(10)),
+                    },
+                    config.monitor_rssi
+                );
+                assert!(!config.enable_bluetooth);
+                assert_eq!(
+                    Some("0.0.0.0:9186".parse::<SocketAddr>().unwrap()),
+                    config.gateway_binding
+                );
+                assert_eq!(
+                    Some(MqttConfig {
+                        host: "broker.local".to_string(),
+                        port: 8883,
+                        topic: "ruuvi/+/+".to_string(),
+                        client_id: "ruuvi-prometheus-rs".to_string(),
+                        username: Some("user".to_string()),
+                        password: Some("secret".to_string()),
+                    }),
+                    config.mqtt
+                );
+                assert_eq!(
+                    Some(CaptureConfig {
+                        path: PathBuf::from("/tmp/capture.jsonl"),
+                        max_bytes: 2048,
+                        max_files: 5,
+                    }),
+                    config.capture
+                );
+                assert_eq!(
+                    Some(ReplayConfig {
+                        path: PathBuf::from("/tmp/replay.jsonl"),
+                        realtime: false,
+                    }),
+                    config.replay
+                );
+                assert_eq!(
+                    BTreeMap::from([
+                        ("aa:bb:cc:dd:ee:ff".to_string(), [0xff; 16]),
+                        (
+                            "cb:b8:33:4c:88:4f".to_string(),
+                            hex_literal::hex!("000102030405060708090a0b0c0d0e0f")
+                        ),
+                    ]),
+                    config.encryption_keys
+                );
+                assert_eq!(
+                    DeviceFilter {
+                        allow: vec![
+                            AddressPattern::Prefix("cb:b8:".to_string()),
+                            AddressPattern::Exact("aa:bb:cc:dd:ee:ff".to_string()),
+                        ],
+                        deny: vec![AddressPattern::Exact("cb:b8:33:4c:88:4f".to_string())],
+                    },
+                    config.device_filter
+                );
+                assert_eq!(
+                    DerivedMetrics {
+                        absolute_humidity: true,
+                        humidex: true,
+                        ..Default::default()
+                    },
+                    config.derived_metrics
+                );
+                assert_eq!(Some(540.0), config.altitude);
+                assert_eq!(15.0, config.orientation_threshold_degrees);
+                assert_eq!(
+                    BatteryModel {
+                        full_volts: 3.1,
+                        low_percent: 25.0,
+                    },
+                    config.battery
+                );
+                assert_eq!(Duration::from_secs(300), config.device_down_after);
+                assert_eq!(Duration::from_secs(7 * 86400), config.device_retention);
             },
         );
     }
+
+    #[test]
+    fn parses_device_registry() {
+        let devices = parse_devices(
+            r#"{
+                "CB:B8:33:4C:88:4F": {
+                    "name": "fridge",
+                    "location": "kitchen",
+                    "labels": {"floor": "1"},
+                    "altitude": 12.5,
+                    "calibration": {
+                        "temperature": {"offset": -0.4},
+                        "humidity": {"offset": 3.0, "gain": 1.02}
+                    }
+                },
+                "aa:bb:cc:dd:ee:ff": {"name": "balcony"}
+            }"#,
+        );
+
+        assert_eq!(
+            BTreeMap::from([
+                (
+                    "aa:bb:cc:dd:ee:ff".to_string(),
+                    DeviceConfig {
+                        alias: DeviceAlias {
+                            name: Some("balcony".to_string()),
+                            ..Default::default()
+                        },
+                        calibration: Calibration::IDENTITY,
+                        altitude: None,
+                    }
+                ),
+                (
+                    "cb:b8:33:4c:88:4f".to_string(),
+                    DeviceConfig {
+                        alias: DeviceAlias {
+                            name: Some("fridge".to_string()),
+                            location: Some("kitchen".to_string()),
+                            labels: BTreeMap::from([("floor".to_string(), "1".to_string())]),
+                        },
+                        calibration: Calibration {
+                            temperature: Correction {
+                                offset: -0.4,
+                                gain: 1.0
+                            },
+                            humidity: Correction {
+                                offset: 3.0,
+                                gain: 1.02
+                            },
+                            ..Default::default()
+                        },
+                        altitude: Some(12.5),
+                    }
+                ),
+            ]),
+            devices
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved")]
+    fn device_registry_rejects_reserved_labels() {
+        parse_devices(r#"{"aa:bb": {"labels": {"device": "x"}}}"#);
+    }
 }
@@ -0,0 +1,95 @@
+//! Allow and deny lists of device addresses.
+
+use crate::source::normalize_device_address;
+
+pub(crate) const REASON_DENIED: &str = "denied";
+pub(crate) const REASON_NOT_ALLOWED: &str = "not_allowed";
+
+/// A full MAC address, or a prefix when ending in `*`, e.g. `c3:4f:*`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AddressPattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl AddressPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = normalize_device_address(pattern.trim());
+        match pattern.strip_suffix('*') {
+            Some(prefix) => AddressPattern::Prefix(prefix.to_string()),
+            None => AddressPattern::Exact(pattern),
+        }
+    }
+
+    fn matches(&self, addr: &str) -> bool {
+        match self {
+            AddressPattern::Exact(exact) => addr == exact,
+            AddressPattern::Prefix(prefix) => addr.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// Devices on the deny list are always dropped. If the allow list is not
+/// empty, only devices on it are accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DeviceFilter {
+    pub allow: Vec<AddressPattern>,
+    pub deny: Vec<AddressPattern>,
+}
+
+impl DeviceFilter {
+    /// Returns the reason for dropping frames of `addr`, if any.
+    pub(crate) fn rejects(&self, addr: &str) -> Option<&'static str> {
+        if self.deny.iter().any(|pattern| pattern.matches(addr)) {
+            return Some(REASON_DENIED);
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| pattern.matches(addr)) {
+            return Some(REASON_NOT_ALLOWED);
+        }
+        None
+    }
+}
+
+pub(crate) fn parse_patterns(value: &str) -> Vec<AddressPattern> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(AddressPattern::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_are_parsed() {
+        assert_eq!(
+            vec![
+                AddressPattern::Exact("aa:bb:cc:dd:ee:ff".to_string()),
+                AddressPattern::Prefix("c3:4f:".to_string()),
+            ],
+            parse_patterns("AA:BB:CC:DD:EE:FF, c3:4f:*,")
+        );
+    }
+
+    #[test]
+    fn empty_filter_accepts_everything() {
+        assert_eq!(None, DeviceFilter::default().rejects("aa:bb:cc:dd:ee:ff"));
+    }
+
+    #[test]
+    fn deny_list_takes_precedence_over_allow_list() {
+        let filter = DeviceFilter {
+            allow: parse_patterns("c3:4f:*"),
+            deny: parse_patterns("c3:4f:00:00:00:01"),
+        };
+
+        assert_eq!(None, filter.rejects("c3:4f:00:00:00:02"));
+        assert_eq!(Some(REASON_DENIED), filter.rejects("c3:4f:00:00:00:01"));
+        assert_eq!(
+            Some(REASON_NOT_ALLOWED),
+            filter.rejects("aa:bb:cc:dd:ee:ff")
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Decoders for the Ruuvi data formats not covered by `ruuvi_decoders`.
+
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, KeyInit, generic_array::GenericArray};
+use ruuvi_decoders::DecodeError;
+
+pub(crate) const FORMAT_V3: u8 = 0x03;
+pub(crate) const FORMAT_V8: u8 = 0x08;
+pub(crate) const FORMAT_C5: u8 = 0xC5;
+
+const V3_PAYLOAD_LENGTH: usize = 14;
+const V8_PAYLOAD_LENGTH: usize = 24;
+const C5_PAYLOAD_LENGTH: usize = 18;
+
+pub(crate) type EncryptionKey = [u8; 16];
+
+/// Data Format 3 (`RAWv1`), broadcast by tags running the factory firmware.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DataFormat3 {
+    /// Relative humidity in %, 0.5% resolution
+    pub humidity: f64,
+    /// Temperature in Celsius, 0.01°C resolution
+    pub temperature: f64,
+    /// Pressure in Pa
+    pub pressure: f64,
+    /// Acceleration in mG
+    pub acceleration_x: i16,
+    pub acceleration_y: i16,
+    pub acceleration_z: i16,
+    /// Battery voltage in mV
+    pub battery_voltage: u16,
+}
+
+/// Data Format C5 (cut `RAWv2`): format 5 without acceleration.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DataFormatC5 {
+    /// Temperature in Celsius, 0.005°C resolution
+    pub temperature: Option<f64>,
+    /// Relative humidity in %, 0.0025% resolution
+    pub humidity: Option<f64>,
+    /// Pressure in Pa
+    pub pressure: Option<f64>,
+    /// Battery voltage in mV
+    pub battery_voltage: Option<u16>,
+    /// Transmit power in dBm
+    pub tx_power: Option<i8>,
+    pub movement_counter: Option<u8>,
+    pub measurement_sequence: Option<u16>,
+    pub mac_address: String,
+}
+
+/// Data Format 8: format 5 without acceleration, AES-128 encrypted per tag.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DataFormat8 {
+    /// Temperature in Celsius, 0.005°C resolution
+    pub temperature: Option<f64>,
+    /// Relative humidity in %, 0.0025% resolution
+    pub humidity: Option<f64>,
+    /// Pressure in Pa
+    pub pressure: Option<f64>,
+    /// Battery voltage in mV
+    pub battery_voltage: Option<u16>,
+    /// Transmit power in dBm
+    pub tx_power: Option<i8>,
+    pub movement_counter: Option<u8>,
+    pub measurement_sequence: Option<u16>,
+    pub mac_address: String,
+}
+
+fn check_header(bytes: &[u8], format: u8, length: usize) -> Result<(), DecodeError> {
+    if bytes.len() < length {
+        return Err(DecodeError::invalid_length(length, bytes.len()));
+    }
+    if bytes[0] != format {
+        return Err(DecodeError::UnsupportedFormat(bytes[0]));
+    }
+    Ok(())
+}
+
+pub(crate) fn decode_v3(bytes: &[u8]) -> Result<DataFormat3, DecodeError> {
+    check_header(bytes, FORMAT_V3, V3_PAYLOAD_LENGTH)?;
+    let get_i16 = |start: usize| i16::from_be_bytes([bytes[start], bytes[start + 1]]);
+    let get_u16 = |start: usize| u16::from_be_bytes([bytes[start], bytes[start + 1]]);
+
+    // Temperature is sign and magnitude: bit 7 of the integer part is the sign.
+    let magnitude = f64::from(bytes[2] & 0x7F) + f64::from(bytes[3]) / 100.0;
+    let temperature = if bytes[2] & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    };
+
+    Ok(DataFormat3 {
+        humidity: f64::from(bytes[1]) / 2.0,
+        temperature,
+        pressure: f64::from(get_u16(4)) + 50_000.0,
+        acceleration_x: get_i16(6),
+        acceleration_y: get_i16(8),
+        acceleration_z: get_i16(10),
+        battery_voltage: get_u16(12),
+    })
+}
+
+pub(crate) fn decode_c5(bytes: &[u8]) -> Result<DataFormatC5, DecodeError> {
+    check_header(bytes, FORMAT_C5, C5_PAYLOAD_LENGTH)?;
+    let get_i16 = |start: usize| i16::from_be_bytes([bytes[start], bytes[start + 1]]);
+    let get_u16 = |start: usize| u16::from_be_bytes([bytes[start], bytes[start + 1]]);
+
+    let raw_temperature = get_i16(1);
+    let raw_humidity = get_u16(3);
+    let raw_pressure = get_u16(5);
+    // 11 bits battery voltage above 1.6V, 5 bits tx power above -40dBm in 2dBm steps
+    let power_info = get_u16(7);
+    let raw_battery = power_info >> 5;
+    let raw_tx_power = power_info & 0b1_1111;
+    let raw_sequence = get_u16(10);
+
+    Ok(DataFormatC5 {
+        temperature: (raw_temperature != i16::MIN).then(|| f64::from(raw_temperature) * 0.005),
+        humidity: (raw_humidity != u16::MAX).then(|| f64::from(raw_humidity) * 0.0025),
+        pressure: (raw_pressure != u16::MAX).then(|| f64::from(raw_pressure) + 50_000.0),
+        battery_voltage: (raw_battery != 0b111_1111_1111).then(|| raw_battery + 1600),
+        tx_power: (raw_tx_power != 0b1_1111).then(|| raw_tx_power as i8 * 2 - 40),
+        movement_counter: (bytes[9] != u8::MAX).then_some(bytes[9]),
+        measurement_sequence: (raw_sequence != u16::MAX).then_some(raw_sequence),
+        mac_address: bytes[12..18].iter().map(|b| format!("{:02x}", b)).collect(),
+    })
+}
+
+/// CRC-8 with polynomial 0x07 and initial value 0x00, as used by format 8.
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+pub(crate) fn decode_v8(bytes: &[u8], key: &EncryptionKey) -> Result<DataFormat8, DecodeError> {
+    check_header(bytes, FORMAT_V8, V8_PAYLOAD_LENGTH)?;
+    let mut block = GenericArray::clone_from_slice(&bytes[1..17]);
+    Aes128::new(GenericArray::from_slice(key)).decrypt_block(&mut block);
+    if crc8(&block) != bytes[17] {
+        return Err(DecodeError::DecryptionFailed("CRC mismatch".to_string()));
+    }
+
+    let get_i16 = |start: usize| i16::from_be_bytes([block[start], block[start + 1]]);
+    let get_u16 = |start: usize| u16::from_be_bytes([block[start], block[start + 1]]);
+    let raw_temperature = get_i16(0);
+    let raw_humidity = get_u16(2);
+    let raw_pressure = get_u16(4);
+    let power_info = get_u16(6);
+    let raw_battery = power_info >> 5;
+    let raw_tx_power = power_info & 0b1_1111;
+    let raw_sequence = get_u16(9);
+
+    Ok(DataFormat8 {
+        temperature: (raw_temperature != i16::MIN).then(|| f64::from(raw_temperature) * 0.005),
+        humidity: (raw_humidity != u16::MAX).then(|| f64::from(raw_humidity) * 0.0025),
+        pressure: (raw_pressure != u16::MAX).then(|| f64::from(raw_pressure) + 50_000.0),
+        battery_voltage: (raw_battery != 0b111_1111_1111).then(|| raw_battery + 1600),
+        tx_power: (raw_tx_power != 0b1_1111).then(|| raw_tx_power as i8 * 2 - 40),
+        movement_counter: (block[8] != u8::MAX).then_some(block[8]),
+        measurement_sequence: (raw_sequence != u16::MAX).then_some(raw_sequence),
+        mac_address: bytes[18..24].iter().map(|b| format!("{:02x}", b)).collect(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use aes::cipher::BlockEncrypt;
+
+    pub(crate) const KEY: EncryptionKey = hex_literal::hex!("000102030405060708090A0B0C0D0E0F");
+
+    /// Builds a format 8 frame with the readings of the format 5 reference frame.
+    pub(crate) fn encrypted_v8_frame(key: &EncryptionKey) -> Vec<u8> {
+        let plaintext = hex_literal::hex!("12FC5394C37CAC364200CDFFFFFFFFFF");
+        let mut block = GenericArray::clone_from_slice(&plaintext);
+        Aes128::new(GenericArray::from_slice(key)).encrypt_block(&mut block);
+        let mut frame = vec![FORMAT_V8];
+        frame.extend_from_slice(&block);
+        frame.push(crc8(&plaintext));
+        frame.extend(hex_literal::hex!("CBB8334C884F"));
+        frame
+    }
+
+    #[test]
+    fn v3_is_decoded() {
+        let payload = hex_literal::hex!("03291A1ECE1EFC18F94202CA0B53");
+
+        let data = decode_v3(&payload).expect("decode v3");
+
+        assert!((data.humidity - 20.5).abs() < f64::EPSILON);
+        assert!((data.temperature - 26.3).abs() < 1e-9);
+        assert!((data.pressure - 102_766.0).abs() < f64::EPSILON);
+        assert_eq!(-1000, data.acceleration_x);
+        assert_eq!(-1726, data.acceleration_y);
+        assert_eq!(714, data.acceleration_z);
+        assert_eq!(2899, data.battery_voltage);
+    }
+
+    #[test]
+    fn v3_negative_temperature_is_decoded() {
+        let payload = hex_literal::hex!("03FF9463FFFF7FFF7FFF7FFFFFFF");
+
+        let data = decode_v3(&payload).expect("decode v3");
+
+        assert!((data.temperature + 20.99).abs() < 1e-9);
+        assert!((data.humidity - 127.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn c5_is_decoded() {
+        let payload = hex_literal::hex!("C512FC5394C37CAC364200CDCBB8334C884F");
+
+        let data = decode_c5(&payload).expect("decode c5");
+
+        assert!((data.temperature.unwrap() - 24.3).abs() < 1e-9);
+        assert!((data.humidity.unwrap() - 53.49).abs() < 1e-9);
+        assert!((data.pressure.unwrap() - 100_044.0).abs() < f64::EPSILON);
+        assert_eq!(Some(2977), data.battery_voltage);
+        assert_eq!(Some(4), data.tx_power);
+        assert_eq!(Some(66), data.movement_counter);
+        assert_eq!(Some(205), data.measurement_sequence);
+        assert_eq!("cbb8334c884f", data.mac_address);
+    }
+
+    #[test]
+    fn c5_invalid_values_are_none() {
+        let payload = hex_literal::hex!("C58000FFFFFFFFFFFFFFFFFFCBB8334C884F");
+
+        let data = decode_c5(&payload).expect("decode c5");
+
+        assert_eq!(None, data.temperature);
+        assert_eq!(None, data.humidity);
+        assert_eq!(None, data.pressure);
+        assert_eq!(None, data.battery_voltage);
+        assert_eq!(None, data.tx_power);
+        assert_eq!(None, data.movement_counter);
+        assert_eq!(None, data.measurement_sequence);
+    }
+
+    #[test]
+    fn wrong_length_or_format_is_rejected() {
+        assert_eq!(
+            Err(DecodeError::invalid_length(14, 3)),
+            decode_v3(&[0x03, 0x00, 0x00])
+        );
+        assert_eq!(
+            Err(DecodeError::UnsupportedFormat(0x05)),
+            decode_c5(&hex_literal::hex!("0512FC5394C37CAC364200CDCBB8334C884F"))
+        );
+    }
+
+    #[test]
+    fn crc8_matches_reference() {
+        assert_eq!(0xF4, crc8(b"123456789"));
+    }
+
+    #[test]
+    fn v8_is_decrypted_and_decoded() {
+        let data = decode_v8(&encrypted_v8_frame(&KEY), &KEY).expect("decode v8");
+
+        assert!((data.temperature.unwrap() - 24.3).abs() < 1e-9);
+        assert!((data.humidity.unwrap() - 53.49).abs() < 1e-9);
+        assert!((data.pressure.unwrap() - 100_044.0).abs() < f64::EPSILON);
+        assert_eq!(Some(2977), data.battery_voltage);
+        assert_eq!(Some(4), data.tx_power);
+        assert_eq!(Some(66), data.movement_counter);
+        assert_eq!(Some(205), data.measurement_sequence);
+        assert_eq!("cbb8334c884f", data.mac_address);
+    }
+
+    #[test]
+    fn v8_known_answer_is_decoded() {
+        // Ciphertext of the FIPS-197 AES-128 example (appendix C.1), so the
+        // frame does not depend on the encryption used by the other tests.
+        // The plaintext 00112233445566778899AABBCCDDEEFF holds the readings.
+        let frame = hex_literal::hex!("0869C4E0D86A7B0430D8CDB78070B4C55A4DCBB8334C884F");
+        let key = hex_literal::hex!("000102030405060708090A0B0C0D0E0F");
+
+        let data = decode_v8(&frame, &key).expect("decode v8");
+
+        assert!((data.temperature.unwrap() - 0.085).abs() < 1e-9);
+        assert!((data.humidity.unwrap() - 21.8875).abs() < 1e-9);
+        assert!((data.pressure.unwrap() - 67_493.0).abs() < f64::EPSILON);
+        assert_eq!(Some(2419), data.battery_voltage);
+        assert_eq!(Some(6), data.tx_power);
+        assert_eq!(Some(136), data.movement_counter);
+        assert_eq!(Some(39338), data.measurement_sequence);
+        assert_eq!("cbb8334c884f", data.mac_address);
+    }
+
+    #[test]
+    fn v8_with_wrong_key_fails_crc() {
+        let wrong_key = [0xAA; 16];
+
+        assert_eq!(
+            Err(DecodeError::DecryptionFailed("CRC mismatch".to_string())),
+            decode_v8(&encrypted_v8_frame(&KEY), &wrong_key)
+        );
+    }
+}
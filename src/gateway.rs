@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use tokio::net::TcpListener;
+
+use crate::source::{
+    Advertisement, AdvertisementSender, AdvertisementSource, decode_hex, normalize_device_address,
+};
+
+#[derive(Debug, Deserialize)]
+struct GatewayPayload {
+    data: GatewayData,
+}
+
+#[derive(Debug, Deserialize)]
+struct GatewayData {
+    gw_mac: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, GatewayTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GatewayTag {
+    rssi: Option<i16>,
+    data: String,
+}
+
+/// Parses a Ruuvi Gateway batch into the contained Ruuvi advertisements.
+pub(crate) fn parse_gateway_payload(body: &[u8]) -> Result<Vec<Advertisement>, serde_json::Error> {
+    let payload: GatewayPayload = serde_json::from_slice(body)?;
+    let gateway = payload.data.gw_mac.as_deref().map(normalize_device_address);
+    let mut advertisements = Vec::new();
+    for (mac, tag) in payload.data.tags {
+        let addr = normalize_device_address(&mac);
+        let Some(raw) = decode_hex(&tag.data) else {
+            eprintln!("Invalid advertisement hex from gateway for {}", addr);
+            continue;
+        };
+        let mut advertisement = Advertisement::new(addr).with_raw_data(&raw);
+        if advertisement.is_ruuvi() {
+            advertisement.gateway = gateway.clone();
+            advertisement.rssi = tag.rssi;
+            advertisements.push(advertisement);
+        }
+    }
+    Ok(advertisements)
+}
+
+async fn handle_request(
+    sink: AdvertisementSender,
+    req: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    if req.method() != Method::POST {
+        return Ok(response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let body = match req.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(err) => {
+            eprintln!("Error reading gateway request: {}", err);
+            return Ok(response(StatusCode::BAD_REQUEST));
+        }
+    };
+    match parse_gateway_payload(&body) {
+        Ok(advertisements) => {
+            for advertisement in advertisements {
+                if sink.send(advertisement).await.is_err() {
+                    return Ok(response(StatusCode::SERVICE_UNAVAILABLE));
+                }
+            }
+            Ok(response(StatusCode::OK))
+        }
+        Err(err) => {
+            eprintln!("Error parsing gateway payload: {}", err);
+            Ok(response(StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+fn response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = status;
+    response
+}
+
+/// Ingestion endpoint for the HTTP POST batches of a Ruuvi Gateway.
+pub(crate) struct GatewayHttpSource {
+    listener: TcpListener,
+}
+
+impl GatewayHttpSource {
+    pub(crate) async fn bind(binding: SocketAddr) -> std::io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(binding).await?,
+        })
+    }
+
+    /// The bound address, which differs from the requested one for port 0.
+    pub(crate) fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl AdvertisementSource for GatewayHttpSource {
+    fn name(&self) -> &'static str {
+        "gateway-http"
+    }
+
+    async fn run(self, sink: AdvertisementSender) -> bluer::Result<()> {
+        loop {
+            let stream = match self.listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    eprintln!("Error accepting gateway connection: {}", err);
+                    continue;
+                }
+            };
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| handle_request(sink.clone(), req));
+                if let Err(err) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    eprintln!("Error serving gateway connection: {}", err);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{RUUVI_COMPANY_ID, spawn_source};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc;
+
+    const GATEWAY_JSON: &str = r#"{
+        "data": {
+            "coordinates": "",
+            "timestamp": 1700000000,
+            "gw_mac": "C8:25:2D:8E:9C:2C",
+            "tags": {
+                "CB:B8:33:4C:88:4F": {
+                    "rssi": -61,
+                    "timestamp": 1700000000,
+                    "data": "0201061BFF99040512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F"
+                },
+                "11:22:33:44:55:66": {
+                    "rssi": -80,
+                    "timestamp": 1700000000,
+                    "data": "02010607FF3412DEADBEEF"
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn gateway_payload_yields_ruuvi_tags_only() {
+        let advertisements = parse_gateway_payload(GATEWAY_JSON.as_bytes()).unwrap();
+
+        assert_eq!(1, advertisements.len());
+        let advertisement = &advertisements[0];
+        assert_eq!("cb:b8:33:4c:88:4f", advertisement.address);
+        assert_eq!(Some("c8:25:2d:8e:9c:2c"), advertisement.gateway.as_deref());
+        assert_eq!(Some(-61), advertisement.rssi);
+        assert_eq!(
+            Some(&hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F").to_vec()),
+            advertisement.manufacturer_data.get(&RUUVI_COMPANY_ID)
+        );
+    }
+
+    #[test]
+    fn invalid_gateway_payload_is_rejected() {
+        assert!(parse_gateway_payload(b"{\"tags\": []}").is_err());
+    }
+
+    async fn send_request(addr: SocketAddr, method: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{} /gateway HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn gateway_listener_accepts_posted_json() {
+        let source = GatewayHttpSource::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let addr = source.local_addr().unwrap();
+        let (sink, mut advertisements) = mpsc::channel(8);
+        spawn_source(source, sink);
+
+        let response = send_request(addr, "POST", GATEWAY_JSON).await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        let response = send_request(addr, "POST", "not json").await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let response = send_request(addr, "GET", "").await;
+        assert!(response.starts_with("HTTP/1.1 405"));
+
+        let advertisement = advertisements.recv().await.expect("advertisement");
+        assert_eq!("cb:b8:33:4c:88:4f", advertisement.address);
+        assert!(advertisements.try_recv().is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! First and last time each device was seen, exported independently of the
+//! sensor gauges so that silent tags stay visible as down.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use tokio::time;
+
+use crate::metrics::Metrics;
+
+/// Upper bound of how often the liveness gauges are refreshed, see [`update_interval`].
+const MAX_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+/// `time::interval` panics on a zero period.
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub(crate) const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(60);
+pub(crate) const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Seen {
+    first: SystemTime,
+    last: SystemTime,
+    /// Reported as lost by BlueZ since it was last seen
+    lost: bool,
+}
+
+/// Shared between the consumer of the advertisement queue, which records when
+/// a device was seen, and the task refreshing the gauges.
+#[derive(Debug, Clone)]
+pub(crate) struct DeviceLiveness {
+    seen: Arc<Mutex<BTreeMap<String, Seen>>>,
+    /// Adapters which BlueZ reported the device as found on, and not lost since
+    found_by: Arc<Mutex<BTreeMap<String, BTreeSet<String>>>>,
+    configured: Arc<BTreeSet<String>>,
+    down_after: Duration,
+    retention: Duration,
+}
+
+impl Default for DeviceLiveness {
+    fn default() -> Self {
+        Self::new(BTreeSet::new(), DEFAULT_DOWN_AFTER, DEFAULT_RETENTION)
+    }
+}
+
+impl DeviceLiveness {
+    /// Devices silent for longer than `down_after` are down, and forgotten
+    /// after `retention` unless they are `configured`.
+    pub(crate) fn new(
+        configured: BTreeSet<String>,
+        down_after: Duration,
+        retention: Duration,
+    ) -> Self {
+        Self {
+            seen: Arc::new(Mutex::new(BTreeMap::new())),
+            found_by: Arc::new(Mutex::new(BTreeMap::new())),
+            configured: Arc::new(configured),
+            down_after,
+            retention,
+        }
+    }
+
+    pub(crate) fn seen(&self, addr: &str, at: SystemTime) {
+        let mut seen = self.seen.lock().unwrap();
+        match seen.get_mut(addr) {
+            Some(device) => {
+                device.last = device.last.max(at);
+                device.lost = false;
+            }
+            None => {
+                seen.insert(
+                    addr.to_string(),
+                    Seen {
+                        first: at,
+                        last: at,
+                        lost: false,
+                    },
+                );
+            }
+        }
+    }
+
+    pub(crate) fn found(&self, addr: &str, adapter: &str) {
+        self.found_by
+            .lock()
+            .unwrap()
+            .entry(addr.to_string())
+            .or_default()
+            .insert(adapter.to_string());
+    }
+
+    /// Marks a device down until it is seen again, once no other adapter has
+    /// it in range anymore. Returns whether the device is down now, which is
+    /// never the case for devices that were not found before.
+    pub(crate) fn lost(&self, addr: &str, adapter: &str) -> bool {
+        let mut found_by = self.found_by.lock().unwrap();
+        let Some(adapters) = found_by.get_mut(addr) else {
+            return false;
+        };
+        if !adapters.remove(adapter) || !adapters.is_empty() {
+            return false;
+        }
+        found_by.remove(addr);
+        if let Some(device) = self.seen.lock().unwrap().get_mut(addr) {
+            device.lost = true;
+        }
+        true
+    }
+
+    /// Sets `ruuvi_device_up`, `ruuvi_device_last_seen_age_seconds` and
+    /// `ruuvi_device_first_seen` for every known device. Configured devices
+    /// which were never seen are down.
+    pub(crate) fn update(&self, metrics: &Metrics, now: SystemTime) {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|addr, device| {
+            let age = now.duration_since(device.last).unwrap_or_default();
+            if age > self.retention && !self.configured.contains(addr) {
+                return false;
+            }
+            metrics.set_device_up(addr, !device.lost && age <= self.down_after);
+            metrics.set_device_last_seen_age(addr, age.as_secs_f64());
+            if let Ok(first) = device.first.duration_since(SystemTime::UNIX_EPOCH) {
+                metrics.set_device_first_seen(addr, first.as_secs_f64());
+            }
+            true
+        });
+        for addr in self
+            .configured
+            .iter()
+            .filter(|addr| !seen.contains_key(*addr))
+        {
+            metrics.set_device_up(addr, false);
+        }
+    }
+}
+
+/// The gauges must be refreshed well within the idle timeout, otherwise they
+/// would be removed in between.
+fn update_interval(idle_timeout: Duration) -> Duration {
+    (idle_timeout / 2).clamp(MIN_UPDATE_INTERVAL, MAX_UPDATE_INTERVAL)
+}
+
+pub(crate) fn spawn_liveness_updater(
+    liveness: DeviceLiveness,
+    metrics: Metrics,
+    idle_timeout: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(update_interval(idle_timeout));
+        loop {
+            interval.tick().await;
+            liveness.update(&metrics, SystemTime::now());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::metrics::{clear, gauge_value, take_snapshot};
+
+    #[test]
+    fn update_interval_is_shorter_than_idle_timeout() {
+        assert_eq!(
+            Duration::from_secs(5),
+            update_interval(Duration::from_secs(60))
+        );
+        assert_eq!(
+            Duration::from_secs(2),
+            update_interval(Duration::from_secs(4))
+        );
+        assert_eq!(Duration::from_millis(100), update_interval(Duration::ZERO));
+    }
+
+    #[test]
+    fn devices_go_down_and_are_forgotten_after_retention() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let liveness = DeviceLiveness::new(
+            BTreeSet::from(["11:00:00:00:00:03".to_string()]),
+            Duration::from_secs(60),
+            Duration::from_secs(3600),
+        );
+
+        liveness.seen("11:00:00:00:00:01", start);
+        liveness.seen("11:00:00:00:00:02", start);
+        liveness.seen("11:00:00:00:00:02", start + Duration::from_secs(100));
+        liveness.update(&metrics, start + Duration::from_secs(130));
+
+        let snapshot = take_snapshot();
+        let up = |device| gauge_value(&snapshot, "ruuvi_device_up", &[("device", device)]);
+        let age = |device| {
+            gauge_value(
+                &snapshot,
+                "ruuvi_device_last_seen_age_seconds",
+                &[("device", device)],
+            )
+        };
+        assert_eq!(Some(0.0), up("11:00:00:00:00:01"));
+        assert_eq!(Some(130.0), age("11:00:00:00:00:01"));
+        assert_eq!(Some(1.0), up("11:00:00:00:00:02"));
+        assert_eq!(Some(30.0), age("11:00:00:00:00:02"));
+        assert_eq!(
+            Some(1_700_000_000.0),
+            gauge_value(
+                &snapshot,
+                "ruuvi_device_first_seen",
+                &[("device", "11:00:00:00:00:02")]
+            )
+        );
+        assert_eq!(Some(0.0), up("11:00:00:00:00:03"));
+        assert_eq!(None, age("11:00:00:00:00:03"));
+
+        liveness.update(&metrics, start + Duration::from_secs(3650));
+
+        assert!(
+            liveness
+                .seen
+                .lock()
+                .unwrap()
+                .contains_key("11:00:00:00:00:02")
+        );
+        assert!(
+            !liveness
+                .seen
+                .lock()
+                .unwrap()
+                .contains_key("11:00:00:00:00:01")
+        );
+    }
+
+    #[test]
+    fn lost_devices_are_down_until_seen_again() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let liveness = DeviceLiveness::default();
+        let up = |liveness: &DeviceLiveness| {
+            liveness.update(&metrics, start + Duration::from_secs(2));
+            gauge_value(
+                &take_snapshot(),
+                "ruuvi_device_up",
+                &[("device", "12:00:00:00:00:01")],
+            )
+        };
+
+        liveness.seen("12:00:00:00:00:01", start);
+        liveness.found("12:00:00:00:00:01", "hci0");
+        liveness.found("12:00:00:00:00:01", "hci1");
+        assert!(!liveness.lost("12:00:00:00:00:01", "hci0"));
+        assert_eq!(Some(1.0), up(&liveness));
+        assert!(liveness.lost("12:00:00:00:00:01", "hci1"));
+        assert_eq!(Some(0.0), up(&liveness));
+
+        liveness.seen("12:00:00:00:00:01", start + Duration::from_secs(1));
+        assert_eq!(Some(1.0), up(&liveness));
+    }
+
+    #[test]
+    fn devices_never_found_are_not_marked_down() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let liveness = DeviceLiveness::default();
+
+        assert!(!liveness.lost("13:00:00:00:00:01", "hci0"));
+        liveness.found("13:00:00:00:00:02", "hci0");
+        assert!(!liveness.lost("13:00:00:00:00:02", "hci1"));
+        liveness.update(&metrics, SystemTime::now());
+
+        let snapshot = take_snapshot();
+        for device in ["13:00:00:00:00:01", "13:00:00:00:00:02"] {
+            assert_eq!(
+                None,
+                gauge_value(&snapshot, "ruuvi_device_up", &[("device", device)])
+            );
+        }
+    }
+}
@@ -1,12 +1,37 @@
+mod battery;
 mod bluetooth;
+mod bthome;
+mod calibration;
+mod capture;
 mod config;
+mod filter;
+mod formats;
+mod gateway;
+mod liveness;
 mod metrics;
+mod mqtt;
+mod orientation;
+mod psychrometrics;
+mod replay;
 mod ruuvi;
+mod sequence;
+mod source;
 #[cfg(test)]
 mod test_utils;
-use crate::bluetooth::{scan_and_listen, setup_adapter_monitor};
+mod xiaomi;
+use futures::future;
+use tokio::sync::mpsc;
+
+use crate::bluetooth::{AdapterSelection, AllAdaptersSource, BluezMonitorSource};
+use crate::capture::CaptureWriter;
 use crate::config::Config;
+use crate::gateway::GatewayHttpSource;
+use crate::liveness::{DeviceLiveness, spawn_liveness_updater};
 use crate::metrics::{Metrics, install_prometheus, spawn_process_collector};
+use crate::mqtt::MqttSource;
+use crate::replay::ReplaySource;
+use crate::ruuvi::Decoder;
+use crate::source::{ADVERTISEMENT_QUEUE_SIZE, process_advertisements, spawn_source};
 
 #[tokio::main]
 async fn main() -> bluer::Result<()> {
@@ -18,11 +43,95 @@ async fn main() -> bluer::Result<()> {
     if config.enable_process_collection {
         spawn_process_collector(config.process_collection_interval);
     }
-    let metrics = Metrics::register();
+    let metrics = Metrics::register().with_devices(config.device_aliases());
+
+    let capture = match &config.capture {
+        Some(capture) => {
+            println!("Capturing advertisements to {}", capture.path.display());
+            Some(CaptureWriter::open(
+                &capture.path,
+                capture.max_bytes,
+                capture.max_files,
+            )?)
+        }
+        None => None,
+    };
+
+    let liveness = DeviceLiveness::new(
+        config.devices.keys().cloned().collect(),
+        config.device_down_after,
+        config.device_retention,
+    );
+    spawn_liveness_updater(liveness.clone(), metrics.clone(), config.idle_timeout);
+
+    let (sink, advertisements) = mpsc::channel(ADVERTISEMENT_QUEUE_SIZE);
+    let decoder = Decoder::new(config.encryption_keys.clone())
+        .with_filter(config.device_filter.clone())
+        .with_calibrations(config.calibrations())
+        .with_derived_metrics(config.derived_metrics)
+        .with_altitudes(config.altitudes(), config.altitude)
+        .with_orientation_threshold(config.orientation_threshold_degrees)
+        .with_battery_model(config.battery)
+        .with_liveness(liveness.clone());
+    tokio::spawn(process_advertisements(
+        advertisements,
+        metrics.clone(),
+        decoder,
+        capture,
+    ));
+
+    if let Some(gateway_binding) = config.gateway_binding {
+        let source = GatewayHttpSource::bind(gateway_binding).await?;
+        println!("Accepting Ruuvi Gateway data on {}", source.local_addr()?);
+        spawn_source(source, sink.clone());
+    }
+
+    if let Some(mqtt) = config.mqtt.clone() {
+        println!(
+            "Subscribing to {} on {}:{}",
+            mqtt.topic, mqtt.host, mqtt.port
+        );
+        spawn_source(MqttSource::new(mqtt), sink.clone());
+    }
+
+    if let Some(replay) = &config.replay {
+        spawn_source(
+            ReplaySource::new(&replay.path, replay.realtime),
+            sink.clone(),
+        );
+    }
+
+    if !config.enable_bluetooth || config.replay.is_some() {
+        std::future::pending::<()>().await;
+    }
 
-    let (adapter, monitor_handle, _monitor_manager) =
-        setup_adapter_monitor(Some(config.adapter_name.as_str())).await?;
-    scan_and_listen(adapter, monitor_handle, metrics).await?;
+    let sources = match &config.adapters {
+        AdapterSelection::All => vec![spawn_source(
+            AllAdaptersSource::new(
+                config.scan_mode,
+                config.monitor_rssi,
+                config.device_filter.clone(),
+                metrics.clone(),
+                liveness,
+            ),
+            sink,
+        )],
+        AdapterSelection::Named(names) => names
+            .iter()
+            .map(|name| {
+                let source = BluezMonitorSource::new(
+                    Some(name.as_str()),
+                    config.scan_mode,
+                    config.monitor_rssi,
+                    config.device_filter.clone(),
+                    metrics.clone(),
+                    liveness.clone(),
+                );
+                spawn_source(source, sink.clone())
+            })
+            .collect(),
+    };
+    future::join_all(sources).await;
 
     Ok(())
 }
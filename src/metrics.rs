@@ -1,21 +1,34 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
 use std::{net::SocketAddr, time::SystemTime};
 
-use metrics::{counter, describe_counter, describe_gauge, gauge};
+use metrics::{Label, counter, describe_counter, describe_gauge, gauge};
 use metrics_exporter_prometheus::PrometheusBuilder;
 use metrics_process::Collector as ProcessCollector;
 use metrics_util::MetricKindMask;
 use tokio::time;
 
-#[derive(Clone, Copy)]
+use crate::config::DeviceAlias;
+
+#[derive(Clone)]
 pub struct Metrics {
     process_start_time: Duration,
+    vendor: Option<&'static str>,
+    devices: Arc<BTreeMap<String, DeviceAlias>>,
 }
 
 impl Metrics {
     const LABEL_DEVICE: &'static str = "device";
     const LABEL_AXIS: &'static str = "axis";
     const LABEL_FORMAT: &'static str = "format";
+    const LABEL_KIND: &'static str = "kind";
+    const LABEL_GATEWAY: &'static str = "gateway";
+    const LABEL_REASON: &'static str = "reason";
+    const LABEL_VENDOR: &'static str = "vendor";
+    const LABEL_NAME: &'static str = "name";
+    const LABEL_LOCATION: &'static str = "location";
+    const LABEL_ADAPTER: &'static str = "adapter";
 
     pub fn register() -> Self {
         Self::describe_metrics();
@@ -25,121 +38,325 @@ impl Metrics {
             process_start_time: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap(),
+            vendor: None,
+            devices: Arc::default(),
         };
         s.set_process_start_time(s.process_start_time);
         s
     }
 
+    /// Metrics of non-Ruuvi sensors share the metric families, but carry an
+    /// additional `vendor` label.
+    pub fn with_vendor(&self, vendor: &'static str) -> Self {
+        Self {
+            vendor: Some(vendor),
+            ..self.clone()
+        }
+    }
+
+    /// Attaches the `name`, `location` and extra labels of known devices to
+    /// all of their metrics.
+    pub fn with_devices(self, devices: BTreeMap<String, DeviceAlias>) -> Self {
+        Self {
+            devices: Arc::new(devices),
+            ..self
+        }
+    }
+
+    fn alias_labels(&self, device: &str) -> Vec<Label> {
+        let mut labels = vec![Label::new(Self::LABEL_DEVICE, device.to_owned())];
+        if let Some(alias) = self.devices.get(device) {
+            if let Some(name) = &alias.name {
+                labels.push(Label::new(Self::LABEL_NAME, name.clone()));
+            }
+            if let Some(location) = &alias.location {
+                labels.push(Label::new(Self::LABEL_LOCATION, location.clone()));
+            }
+            for (key, value) in &alias.labels {
+                labels.push(Label::new(key.clone(), value.clone()));
+            }
+        }
+        labels
+    }
+
+    fn device_labels(&self, device: &str) -> Vec<Label> {
+        let mut labels = self.alias_labels(device);
+        if let Some(vendor) = self.vendor {
+            labels.push(Label::new(Self::LABEL_VENDOR, vendor));
+        }
+        labels
+    }
+
+    /// Info gauge carrying the alias of a device, for joins in PromQL.
+    pub fn set_device_info(&self, device: &str) {
+        if self.devices.contains_key(device) {
+            gauge!("ruuvi_device_info", self.alias_labels(device)).set(1.0);
+        }
+    }
+
+    fn device_labels_with(&self, device: &str, key: &'static str, value: &str) -> Vec<Label> {
+        let mut labels = self.device_labels(device);
+        labels.push(Label::new(key, value.to_owned()));
+        labels
+    }
+
     pub fn inc_ruuvi_frames(&self, device: &str, format: &str) {
-        let device_label = device.to_owned();
-        let format_label = format.to_owned();
-        counter!("ruuvi_frames_total", Self::LABEL_DEVICE => device_label, Self::LABEL_FORMAT => format_label).increment(1);
+        counter!(
+            "ruuvi_frames_total",
+            self.device_labels_with(device, Self::LABEL_FORMAT, format)
+        )
+        .increment(1);
+    }
+
+    /// Only the per-receiver series carry the `gateway` label, the decoded
+    /// readings of a tag are merged across gateways.
+    pub fn inc_gateway_frames(&self, device: &str, gateway: &str) {
+        counter!(
+            "ruuvi_gateway_frames_total",
+            self.device_labels_with(device, Self::LABEL_GATEWAY, gateway)
+        )
+        .increment(1);
+    }
+
+    pub fn inc_decryption_errors(&self, device: &str, reason: &str) {
+        counter!(
+            "ruuvi_decryption_errors_total",
+            self.device_labels_with(device, Self::LABEL_REASON, reason)
+        )
+        .increment(1);
+    }
+
+    pub fn inc_frames_missed(&self, device: &str, format: &str, missed: u64) {
+        counter!(
+            "ruuvi_frames_missed_total",
+            self.device_labels_with(device, Self::LABEL_FORMAT, format)
+        )
+        .increment(missed);
+    }
+
+    pub fn inc_frames_duplicate(&self, device: &str, format: &str) {
+        counter!(
+            "ruuvi_frames_duplicate_total",
+            self.device_labels_with(device, Self::LABEL_FORMAT, format)
+        )
+        .increment(1);
+    }
+
+    /// Not labeled by device, to not export series of filtered devices.
+    pub fn inc_frames_dropped(&self, reason: &str) {
+        let reason_label = reason.to_owned();
+        counter!("ruuvi_frames_dropped_total", Self::LABEL_REASON => reason_label).increment(1);
     }
 
     pub fn set_temperature(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_temperature_celsius", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_temperature_celsius", self.device_labels(device)).set(value);
     }
 
     pub fn set_humidity(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_humidity_ratio", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_humidity_ratio", self.device_labels(device)).set(value);
     }
 
     pub fn set_dew_point(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_dew_point_celsius", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_dew_point_celsius", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_pressure_sea_level(&self, device: &str, value: f64) {
+        gauge!("ruuvi_pressure_sea_level_hpa", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_absolute_humidity(&self, device: &str, value: f64) {
+        gauge!("ruuvi_absolute_humidity_g_m3", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_vapour_pressure_deficit(&self, device: &str, value: f64) {
+        gauge!(
+            "ruuvi_vapour_pressure_deficit_kpa",
+            self.device_labels(device)
+        )
+        .set(value);
+    }
+
+    pub fn set_humidex(&self, device: &str, value: f64) {
+        gauge!("ruuvi_humidex", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_heat_index(&self, device: &str, value: f64) {
+        gauge!("ruuvi_heat_index_celsius", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_air_density(&self, device: &str, value: f64) {
+        gauge!("ruuvi_air_density_kg_m3", self.device_labels(device)).set(value);
     }
 
     pub fn set_pressure(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_pressure_hpa", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_pressure_hpa", self.device_labels(device)).set(value);
     }
 
     pub fn set_acceleration(&self, device: &str, axis: &str, value: f64) {
-        let device_label = device.to_owned();
-        let axis_label = axis.to_owned();
         gauge!(
             "ruuvi_acceleration_g",
-            Self::LABEL_DEVICE => device_label,
-            Self::LABEL_AXIS => axis_label
+            self.device_labels_with(device, Self::LABEL_AXIS, axis)
         )
         .set(value);
     }
 
+    pub fn set_acceleration_magnitude(&self, device: &str, value: f64) {
+        gauge!("ruuvi_acceleration_magnitude_g", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_pitch(&self, device: &str, value: f64) {
+        gauge!("ruuvi_pitch_degrees", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_roll(&self, device: &str, value: f64) {
+        gauge!("ruuvi_roll_degrees", self.device_labels(device)).set(value);
+    }
+
+    pub fn inc_orientation_changes(&self, device: &str, changes: u64) {
+        counter!(
+            "ruuvi_orientation_changes_total",
+            self.device_labels(device)
+        )
+        .increment(changes);
+    }
+
     pub fn set_voltage(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_battery_volts", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_battery_volts", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_battery_percent(&self, device: &str, value: f64) {
+        gauge!("ruuvi_battery_percent", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_battery_low(&self, device: &str, low: bool) {
+        gauge!("ruuvi_battery_low", self.device_labels(device)).set(f64::from(u8::from(low)));
+    }
+
+    pub fn set_device_up(&self, device: &str, up: bool) {
+        gauge!("ruuvi_device_up", self.device_labels(device)).set(f64::from(u8::from(up)));
+    }
+
+    pub fn set_device_last_seen_age(&self, device: &str, seconds: f64) {
+        gauge!(
+            "ruuvi_device_last_seen_age_seconds",
+            self.device_labels(device)
+        )
+        .set(seconds);
+    }
+
+    pub fn set_device_first_seen(&self, device: &str, timestamp: f64) {
+        gauge!("ruuvi_device_first_seen", self.device_labels(device)).set(timestamp);
     }
 
-    pub fn set_signal_rssi(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_rssi_dbm", Self::LABEL_DEVICE => device_label).set(value);
+    pub fn set_tracked_devices(&self, adapter: &str, count: usize) {
+        let adapter_label = adapter.to_owned();
+        gauge!("ruuvi_tracked_devices", Self::LABEL_ADAPTER => adapter_label).set(count as f64);
+    }
+
+    pub fn set_adapter_up(&self, adapter: &str, up: bool) {
+        let adapter_label = adapter.to_owned();
+        gauge!("ruuvi_adapter_up", Self::LABEL_ADAPTER => adapter_label)
+            .set(f64::from(u8::from(up)));
+    }
+
+    pub fn inc_adapter_reconnects(&self, adapter: &str, reconnects: u64) {
+        let adapter_label = adapter.to_owned();
+        counter!("ruuvi_adapter_reconnects_total", Self::LABEL_ADAPTER => adapter_label)
+            .increment(reconnects);
+    }
+
+    /// Keeps the adapter series from being removed after the idle timeout,
+    /// also while the adapter is down and no reconnect is counted.
+    pub fn refresh_adapter(&self, adapter: &str, up: bool) {
+        self.set_adapter_up(adapter, up);
+        let adapter_label = adapter.to_owned();
+        counter!("ruuvi_adapter_reconnects_total", Self::LABEL_ADAPTER => adapter_label)
+            .increment(0);
+    }
+
+    /// Labeled by the receiving adapter if received over Bluetooth.
+    pub fn set_signal_rssi(&self, device: &str, adapter: Option<&str>, value: f64) {
+        let labels = match adapter {
+            Some(adapter) => self.device_labels_with(device, Self::LABEL_ADAPTER, adapter),
+            None => self.device_labels(device),
+        };
+        gauge!("ruuvi_rssi_dbm", labels).set(value);
+    }
+
+    pub fn set_gateway_rssi(&self, device: &str, gateway: &str, value: f64) {
+        gauge!(
+            "ruuvi_gateway_rssi_dbm",
+            self.device_labels_with(device, Self::LABEL_GATEWAY, gateway)
+        )
+        .set(value);
     }
 
     pub fn set_tx_power(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_txpower_dbm", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_txpower_dbm", self.device_labels(device)).set(value);
     }
 
     pub fn set_seqno(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_seqno_current", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_seqno_current", self.device_labels(device)).set(value);
     }
 
     pub fn set_pm1_0(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_pm1_0_ug_m3", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_pm1_0_ug_m3", self.device_labels(device)).set(value);
     }
 
     pub fn set_pm2_5(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_pm2_5_ug_m3", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_pm2_5_ug_m3", self.device_labels(device)).set(value);
     }
 
     pub fn set_pm4_0(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_pm4_0_ug_m3", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_pm4_0_ug_m3", self.device_labels(device)).set(value);
     }
 
     pub fn set_pm10_0(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_pm10_0_ug_m3", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_pm10_0_ug_m3", self.device_labels(device)).set(value);
     }
 
     pub fn set_co2(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_co2_ppm", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_co2_ppm", self.device_labels(device)).set(value);
     }
 
     pub fn set_voc(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_voc_index", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_voc_index", self.device_labels(device)).set(value);
     }
 
     pub fn set_nox(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_nox_index", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_nox_index", self.device_labels(device)).set(value);
     }
 
     pub fn set_air_quality_index(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_air_quality_index", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_air_quality_index", self.device_labels(device)).set(value);
     }
 
     pub fn set_calibrating(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_air_calibrating", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_air_calibrating", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_illuminance(&self, device: &str, value: f64) {
+        gauge!("ruuvi_illuminance_lux", self.device_labels(device)).set(value);
+    }
+
+    pub fn set_sound(&self, device: &str, kind: &str, value: f64) {
+        gauge!(
+            "ruuvi_sound_dba",
+            self.device_labels_with(device, Self::LABEL_KIND, kind)
+        )
+        .set(value);
     }
 
     pub fn set_last_updated(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_last_updated", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_last_updated", self.device_labels(device)).set(value);
     }
 
     pub fn set_move_count(&self, device: &str, value: f64) {
-        let device_label = device.to_owned();
-        gauge!("ruuvi_movecount_total", Self::LABEL_DEVICE => device_label).set(value);
+        gauge!("ruuvi_movecount", self.device_labels(device)).set(value);
+    }
+
+    pub fn inc_movements(&self, device: &str, movements: u64) {
+        counter!("ruuvi_movements_total", self.device_labels(device)).increment(movements);
     }
 
     pub fn set_process_start_time(&self, start_time: Duration) {
@@ -148,19 +365,97 @@ impl Metrics {
 
     fn describe_metrics() {
         describe_counter!("ruuvi_frames_total", "Total Ruuvi frames received");
+        describe_counter!(
+            "ruuvi_gateway_frames_total",
+            "Total Ruuvi frames received per gateway"
+        );
+        describe_counter!(
+            "ruuvi_decryption_errors_total",
+            "Total encrypted Ruuvi frames that could not be decrypted"
+        );
+        describe_counter!(
+            "ruuvi_frames_missed_total",
+            "Total frames not received according to the measurement sequence number"
+        );
+        describe_counter!(
+            "ruuvi_frames_duplicate_total",
+            "Total repeated frames with an unchanged measurement sequence number"
+        );
+        describe_counter!(
+            "ruuvi_frames_dropped_total",
+            "Total frames dropped by the device allow and deny lists"
+        );
         describe_gauge!("ruuvi_temperature_celsius", "Ruuvi tag sensor temperature");
         describe_gauge!("ruuvi_humidity_ratio", "Ruuvi tag sensor relative humidity");
         describe_gauge!(
             "ruuvi_dew_point_celsius",
             "Calculated dew point derived from temperature and humidity"
         );
+        describe_gauge!(
+            "ruuvi_absolute_humidity_g_m3",
+            "Calculated absolute humidity in g/m3"
+        );
+        describe_gauge!(
+            "ruuvi_vapour_pressure_deficit_kpa",
+            "Calculated vapour pressure deficit in kPa"
+        );
+        describe_gauge!("ruuvi_humidex", "Calculated humidex");
+        describe_gauge!("ruuvi_heat_index_celsius", "Calculated heat index");
+        describe_gauge!("ruuvi_air_density_kg_m3", "Calculated density of moist air");
         describe_gauge!("ruuvi_pressure_hpa", "Ruuvi tag sensor air pressure");
+        describe_gauge!(
+            "ruuvi_pressure_sea_level_hpa",
+            "Air pressure reduced to sea level"
+        );
         describe_gauge!(
             "ruuvi_acceleration_g",
             "Ruuvi tag sensor acceleration X/Y/Z"
         );
+        describe_gauge!(
+            "ruuvi_acceleration_magnitude_g",
+            "Magnitude of the Ruuvi tag acceleration"
+        );
+        describe_gauge!(
+            "ruuvi_pitch_degrees",
+            "Ruuvi tag pitch derived from gravity"
+        );
+        describe_gauge!("ruuvi_roll_degrees", "Ruuvi tag roll derived from gravity");
+        describe_counter!(
+            "ruuvi_orientation_changes_total",
+            "Total changes of the Ruuvi tag orientation beyond the threshold"
+        );
         describe_gauge!("ruuvi_battery_volts", "Ruuvi tag battery voltage");
+        describe_gauge!("ruuvi_battery_percent", "Battery level in percent");
+        describe_gauge!(
+            "ruuvi_device_up",
+            "1 if the device was seen recently, 0 if it went silent"
+        );
+        describe_gauge!(
+            "ruuvi_device_last_seen_age_seconds",
+            "Seconds since the device was last seen"
+        );
+        describe_gauge!(
+            "ruuvi_device_first_seen",
+            "Unix time when the device was first seen"
+        );
+        describe_gauge!(
+            "ruuvi_adapter_up",
+            "1 if the advertisement monitor of the Bluetooth adapter is running"
+        );
+        describe_counter!(
+            "ruuvi_adapter_reconnects_total",
+            "Total times the Bluetooth adapter was set up again after it was lost"
+        );
+        describe_gauge!(
+            "ruuvi_tracked_devices",
+            "Number of devices currently tracked by the Bluetooth adapter"
+        );
+        describe_gauge!("ruuvi_battery_low", "1 if the battery should be replaced");
         describe_gauge!("ruuvi_rssi_dbm", "Ruuvi tag received signal strength RSSI");
+        describe_gauge!(
+            "ruuvi_gateway_rssi_dbm",
+            "Ruuvi tag received signal strength RSSI per gateway"
+        );
         describe_gauge!("ruuvi_txpower_dbm", "Ruuvi transmit power in dBm");
         describe_gauge!("ruuvi_seqno_current", "Ruuvi frame sequence number");
         describe_gauge!("ruuvi_pm1_0_ug_m3", "Ruuvi PM1.0 concentration in ug/m3");
@@ -172,9 +467,22 @@ impl Metrics {
         describe_gauge!("ruuvi_nox_index", "Ruuvi NOx index");
         describe_gauge!("ruuvi_air_quality_index", "Ruuvi Air Quality Index");
         describe_gauge!("ruuvi_air_calibrating", "Ruuvi calibrating");
+        describe_gauge!("ruuvi_illuminance_lux", "Ruuvi illuminance in lux");
+        describe_gauge!(
+            "ruuvi_sound_dba",
+            "Ruuvi sound level instant/average/peak in dBA"
+        );
         describe_gauge!("ruuvi_last_updated", "Last update of RuuviTag");
+        describe_gauge!(
+            "ruuvi_device_info",
+            "Name, location and extra labels of a configured device"
+        );
         describe_gauge!("rust_info", "Info about the Rust version");
-        describe_gauge!("ruuvi_movecount_total", "Ruuvi movement counter");
+        describe_gauge!("ruuvi_movecount", "Raw Ruuvi movement counter");
+        describe_counter!(
+            "ruuvi_movements_total",
+            "Total movements detected by the accelerometer"
+        );
         describe_gauge!("process_start_time", "Start time of the process");
     }
 
@@ -251,8 +559,9 @@ mod tests {
         let metrics = Metrics::register();
 
         metrics.inc_ruuvi_frames("aa:bb", "5");
-        metrics.set_signal_rssi("aa:bb", -55.0);
+        metrics.set_signal_rssi("aa:bb", None, -55.0);
         metrics.set_acceleration("aa:bb", "Z", 0.123);
+        metrics.set_sound("aa:bb", "peak", 71.4);
 
         let snapshot = take_snapshot();
 
@@ -276,6 +585,52 @@ mod tests {
             )
             .is_some_and(|v| (v - 0.123).abs() < f64::EPSILON)
         );
+        assert!(
+            gauge_value(
+                &snapshot,
+                "ruuvi_sound_dba",
+                &[("device", "aa:bb"), ("kind", "peak")]
+            )
+            .is_some_and(|v| (v - 71.4).abs() < f64::EPSILON)
+        );
+    }
+
+    #[test]
+    fn refreshing_an_adapter_keeps_its_reconnects() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+
+        metrics.refresh_adapter("hci7", false);
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(0),
+            counter_value(
+                &snapshot,
+                "ruuvi_adapter_reconnects_total",
+                &[("adapter", "hci7")]
+            )
+        );
+        assert_eq!(
+            Some(0.0),
+            gauge_value(&snapshot, "ruuvi_adapter_up", &[("adapter", "hci7")])
+        );
+
+        metrics.inc_adapter_reconnects("hci7", 1);
+        metrics.refresh_adapter("hci7", true);
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_adapter_reconnects_total",
+                &[("adapter", "hci7")]
+            )
+        );
+        assert_eq!(
+            Some(1.0),
+            gauge_value(&snapshot, "ruuvi_adapter_up", &[("adapter", "hci7")])
+        );
     }
 
     #[test]
@@ -292,6 +647,7 @@ mod tests {
         metrics.set_voc("aa:bb", 50.0);
         metrics.set_nox("aa:bb", 25.0);
         metrics.set_calibrating("aa:bb", 1.0);
+        metrics.set_illuminance("aa:bb", 320.5);
         metrics.set_last_updated("aa:bb", 123.0);
         metrics.set_move_count("aa:bb", 7.0);
         metrics.set_voltage("aa:bb", 2.9);
@@ -315,10 +671,70 @@ mod tests {
         expect("ruuvi_voc_index", 50.0);
         expect("ruuvi_nox_index", 25.0);
         expect("ruuvi_air_calibrating", 1.0);
+        expect("ruuvi_illuminance_lux", 320.5);
         expect("ruuvi_last_updated", 123.0);
-        expect("ruuvi_movecount_total", 7.0);
+        expect("ruuvi_movecount", 7.0);
         expect("ruuvi_battery_volts", 2.9);
         expect("ruuvi_txpower_dbm", -4.0);
         expect("ruuvi_seqno_current", 42.0);
     }
+
+    #[test]
+    fn device_aliases_are_attached_to_all_metrics() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register().with_devices(BTreeMap::from([(
+            "aa:bb".to_string(),
+            DeviceAlias {
+                name: Some("fridge".to_string()),
+                location: None,
+                labels: BTreeMap::from([("floor".to_string(), "1".to_string())]),
+            },
+        )]));
+
+        metrics.set_temperature("aa:bb", 4.5);
+        metrics.set_sound("aa:bb", "peak", 71.4);
+        metrics.set_temperature("cc:dd", 21.0);
+        metrics.set_device_info("aa:bb");
+        metrics.set_device_info("cc:dd");
+
+        let snapshot = take_snapshot();
+        let labels = [("device", "aa:bb"), ("name", "fridge"), ("floor", "1")];
+        assert!(
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &labels)
+                .is_some_and(|v| (v - 4.5).abs() < f64::EPSILON)
+        );
+        assert!(
+            gauge_value(
+                &snapshot,
+                "ruuvi_sound_dba",
+                &[
+                    ("device", "aa:bb"),
+                    ("name", "fridge"),
+                    ("floor", "1"),
+                    ("kind", "peak")
+                ]
+            )
+            .is_some_and(|v| (v - 71.4).abs() < f64::EPSILON)
+        );
+        assert!(
+            gauge_value(
+                &snapshot,
+                "ruuvi_temperature_celsius",
+                &[("device", "cc:dd")]
+            )
+            .is_some_and(|v| (v - 21.0).abs() < f64::EPSILON)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_device_info", &labels)
+                .is_some_and(|v| (v - 1.0).abs() < f64::EPSILON)
+        );
+        assert_eq!(
+            1,
+            snapshot
+                .iter()
+                .filter(|(name, _, _)| name == "ruuvi_device_info")
+                .count()
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+
+use crate::config::MqttConfig;
+use crate::source::{
+    Advertisement, AdvertisementSender, AdvertisementSource, decode_hex, normalize_device_address,
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Message body published by a Ruuvi Gateway for a single advertisement.
+#[derive(Debug, Deserialize)]
+struct GatewayMessage {
+    gw_mac: Option<String>,
+    rssi: Option<i16>,
+    data: String,
+}
+
+/// Splits a `ruuvi/<gw_mac>/<tag_mac>` topic into gateway and tag address.
+fn addresses_from_topic(topic: &str) -> Option<(&str, &str)> {
+    let mut segments = topic.rsplit('/');
+    let tag = segments.next().filter(|s| !s.is_empty())?;
+    let gateway = segments.next().filter(|s| !s.is_empty())?;
+    Some((gateway, tag))
+}
+
+pub(crate) fn parse_mqtt_message(
+    topic: &str,
+    payload: &[u8],
+) -> Result<Option<Advertisement>, serde_json::Error> {
+    let Some((topic_gateway, tag)) = addresses_from_topic(topic) else {
+        eprintln!("Unexpected MQTT topic: {}", topic);
+        return Ok(None);
+    };
+    let message: GatewayMessage = serde_json::from_slice(payload)?;
+    let addr = normalize_device_address(tag);
+    let gateway = normalize_device_address(message.gw_mac.as_deref().unwrap_or(topic_gateway));
+
+    let Some(raw) = decode_hex(&message.data) else {
+        eprintln!(
+            "Invalid advertisement hex from gateway {} for {}",
+            gateway, addr
+        );
+        return Ok(None);
+    };
+    let mut advertisement = Advertisement::new(addr).with_raw_data(&raw);
+    if !advertisement.is_ruuvi() {
+        return Ok(None);
+    }
+    advertisement.gateway = Some(gateway);
+    advertisement.rssi = message.rssi;
+    Ok(Some(advertisement))
+}
+
+fn mqtt_options(config: &MqttConfig) -> MqttOptions {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+    options
+}
+
+/// Advertisements published by Ruuvi Gateways to an MQTT broker.
+pub(crate) struct MqttSource {
+    config: MqttConfig,
+}
+
+impl MqttSource {
+    pub(crate) fn new(config: MqttConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl AdvertisementSource for MqttSource {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    /// Subscribes to the configured topic filter, the subscription is renewed
+    /// after every reconnect.
+    async fn run(self, sink: AdvertisementSender) -> bluer::Result<()> {
+        let (client, mut eventloop) = AsyncClient::new(mqtt_options(&self.config), 64);
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    if let Err(err) = client.try_subscribe(&self.config.topic, QoS::AtMostOnce) {
+                        eprintln!("Error subscribing to {}: {}", self.config.topic, err);
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    match parse_mqtt_message(&publish.topic, &publish.payload) {
+                        Ok(Some(advertisement)) => {
+                            if sink.send(advertisement).await.is_err() {
+                                return Ok(());
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            eprintln!("Error parsing MQTT message on {}: {}", publish.topic, err)
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("MQTT connection error: {}", err);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::RUUVI_COMPANY_ID;
+
+    const MESSAGE: &str = r#"{
+        "gw_mac": "C8:25:2D:8E:9C:2C",
+        "rssi": -62,
+        "aoa": [],
+        "gwts": "1700000000",
+        "ts": "1700000000",
+        "data": "0201061BFF99040512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F",
+        "coords": ""
+    }"#;
+
+    #[test]
+    fn topic_is_split_into_gateway_and_tag() {
+        assert_eq!(
+            Some(("C8:25:2D:8E:9C:2C", "CB:B8:33:4C:88:4F")),
+            addresses_from_topic("ruuvi/C8:25:2D:8E:9C:2C/CB:B8:33:4C:88:4F")
+        );
+        assert_eq!(None, addresses_from_topic("ruuvi"));
+        assert_eq!(None, addresses_from_topic("ruuvi/gw/"));
+    }
+
+    #[test]
+    fn mqtt_message_yields_advertisement_with_gateway() {
+        let advertisement = parse_mqtt_message(
+            "ruuvi/C8:25:2D:8E:9C:2C/CB:B8:33:4C:88:4F",
+            MESSAGE.as_bytes(),
+        )
+        .unwrap()
+        .expect("advertisement");
+
+        assert_eq!("cb:b8:33:4c:88:4f", advertisement.address);
+        assert_eq!(Some("c8:25:2d:8e:9c:2c"), advertisement.gateway.as_deref());
+        assert_eq!(Some(-62), advertisement.rssi);
+        assert_eq!(
+            Some(&hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F").to_vec()),
+            advertisement.manufacturer_data.get(&RUUVI_COMPANY_ID)
+        );
+    }
+
+    #[test]
+    fn non_ruuvi_mqtt_message_is_skipped() {
+        let advertisement = parse_mqtt_message(
+            "ruuvi/gw/11:22:33:44:55:66",
+            br#"{"gw_mac": "gw", "rssi": -70, "data": "02010607FF3412DEADBEEF"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(None, advertisement);
+        assert!(parse_mqtt_message("ruuvi/gw/tag", b"not json").is_err());
+    }
+}
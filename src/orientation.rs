@@ -0,0 +1,115 @@
+//! Tilt of a tag derived from the direction of gravity in its accelerometer readings.
+
+use std::collections::BTreeMap;
+
+pub(crate) const DEFAULT_ORIENTATION_THRESHOLD_DEGREES: f64 = 30.0;
+
+/// Acceleration in g along the X, Y and Z axis of the tag.
+pub(crate) type Acceleration = [f64; 3];
+
+pub(crate) fn magnitude(a: &Acceleration) -> f64 {
+    (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
+
+/// Rotation around the Y axis, positive when the X axis points upwards.
+pub(crate) fn pitch_degrees(a: &Acceleration) -> f64 {
+    a[0].atan2((a[1] * a[1] + a[2] * a[2]).sqrt()).to_degrees()
+}
+
+/// Rotation around the X axis, 0 when lying flat with the Z axis upwards.
+pub(crate) fn roll_degrees(a: &Acceleration) -> f64 {
+    a[1].atan2(a[2]).to_degrees()
+}
+
+fn angle_between_degrees(a: &Acceleration, b: &Acceleration) -> Option<f64> {
+    let norm = magnitude(a) * magnitude(b);
+    if norm == 0.0 {
+        return None;
+    }
+    let cos = (a[0] * b[0] + a[1] * b[1] + a[2] * b[2]) / norm;
+    Some(cos.clamp(-1.0, 1.0).acos().to_degrees())
+}
+
+/// Detects when a tag is tilted by more than a threshold compared to the
+/// orientation at its previous change, e.g. an opened lid.
+#[derive(Debug)]
+pub(crate) struct OrientationTracker {
+    threshold_degrees: f64,
+    reference: BTreeMap<String, Acceleration>,
+}
+
+impl Default for OrientationTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_ORIENTATION_THRESHOLD_DEGREES)
+    }
+}
+
+impl OrientationTracker {
+    pub(crate) fn new(threshold_degrees: f64) -> Self {
+        Self {
+            threshold_degrees,
+            reference: BTreeMap::new(),
+        }
+    }
+
+    /// Returns true if the orientation changed. The first reading of a device
+    /// only sets the reference.
+    pub(crate) fn observe(&mut self, addr: &str, acceleration: Acceleration) -> bool {
+        let Some(reference) = self.reference.get(addr) else {
+            self.reference.insert(addr.to_string(), acceleration);
+            return false;
+        };
+        match angle_between_degrees(reference, &acceleration) {
+            Some(angle) if angle > self.threshold_degrees => {
+                self.reference.insert(addr.to_string(), acceleration);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilt_angles_follow_gravity() {
+        // (acceleration, pitch, roll)
+        for (acceleration, pitch, roll) in [
+            ([0.0, 0.0, 1.0], 0.0, 0.0),
+            ([1.0, 0.0, 0.0], 90.0, 0.0),
+            ([0.0, 1.0, 0.0], 0.0, 90.0),
+            ([0.0, 0.0, -1.0], 0.0, 180.0),
+            ([0.5, 0.0, 0.866], 30.0, 0.0),
+        ] {
+            assert!((pitch_degrees(&acceleration) - pitch).abs() < 0.01);
+            assert!((roll_degrees(&acceleration) - roll).abs() < 0.01);
+        }
+        assert!((magnitude(&[0.004, -0.004, 1.036]) - 1.036).abs() < 0.001);
+    }
+
+    #[test]
+    fn orientation_change_is_detected_above_threshold() {
+        let mut tracker = OrientationTracker::new(30.0);
+
+        assert!(!tracker.observe("aa", [0.0, 0.0, 1.0]));
+        // small wobble
+        assert!(!tracker.observe("aa", [0.2, 0.0, 0.98]));
+        // lid opened
+        assert!(tracker.observe("aa", [0.0, 1.0, 0.0]));
+        // stays open
+        assert!(!tracker.observe("aa", [0.0, 0.99, 0.05]));
+        // closed again
+        assert!(tracker.observe("aa", [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn free_fall_does_not_change_orientation() {
+        let mut tracker = OrientationTracker::new(30.0);
+
+        tracker.observe("aa", [0.0, 0.0, 1.0]);
+
+        assert!(!tracker.observe("aa", [0.0, 0.0, 0.0]));
+    }
+}
@@ -0,0 +1,229 @@
+//! Quantities derived from temperature, relative humidity and pressure.
+
+use crate::metrics::Metrics;
+
+const MAGNUS_A_HPA: f64 = 6.112;
+const MAGNUS_B: f64 = 17.62;
+const MAGNUS_C: f64 = 243.12;
+const KELVIN: f64 = 273.15;
+/// Specific gas constants in J/(kg·K)
+const R_DRY_AIR: f64 = 287.058;
+const R_WATER_VAPOUR: f64 = 461.495;
+/// Temperature lapse rate of the standard atmosphere in K/m
+const LAPSE_RATE: f64 = 0.0065;
+const BAROMETRIC_EXPONENT: f64 = 5.257;
+
+/// Which derived metrics are exported in addition to the dew point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DerivedMetrics {
+    pub absolute_humidity: bool,
+    pub vapour_pressure_deficit: bool,
+    pub humidex: bool,
+    pub heat_index: bool,
+    pub air_density: bool,
+}
+
+impl DerivedMetrics {
+    /// Parses a comma separated list like `absolute_humidity,vpd`.
+    pub fn parse(value: &str) -> Self {
+        let mut derived = Self::default();
+        for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name {
+                "absolute_humidity" => derived.absolute_humidity = true,
+                "vpd" => derived.vapour_pressure_deficit = true,
+                "humidex" => derived.humidex = true,
+                "heat_index" => derived.heat_index = true,
+                "air_density" => derived.air_density = true,
+                _ => panic!("unknown derived metric: {}", name),
+            }
+        }
+        derived
+    }
+}
+
+/// Saturation vapour pressure over water in hPa (Magnus formula).
+fn saturation_vapour_pressure_hpa(temperature_c: f64) -> f64 {
+    MAGNUS_A_HPA * (MAGNUS_B * temperature_c / (MAGNUS_C + temperature_c)).exp()
+}
+
+fn vapour_pressure_hpa(temperature_c: f64, humidity_ratio: f64) -> f64 {
+    humidity_ratio * saturation_vapour_pressure_hpa(temperature_c)
+}
+
+/// Mass of water vapour per volume of air in g/m³.
+pub(crate) fn absolute_humidity_g_m3(temperature_c: f64, humidity_ratio: f64) -> f64 {
+    let vapour_pressure_pa = vapour_pressure_hpa(temperature_c, humidity_ratio) * 100.0;
+    vapour_pressure_pa / (R_WATER_VAPOUR * (temperature_c + KELVIN)) * 1000.0
+}
+
+/// Difference between saturation and actual vapour pressure in kPa.
+pub(crate) fn vapour_pressure_deficit_kpa(temperature_c: f64, humidity_ratio: f64) -> f64 {
+    saturation_vapour_pressure_hpa(temperature_c) * (1.0 - humidity_ratio) / 10.0
+}
+
+/// Canadian humidex, dimensionless but comparable to °C.
+pub(crate) fn humidex(temperature_c: f64, humidity_ratio: f64) -> f64 {
+    temperature_c + 5.0 / 9.0 * (vapour_pressure_hpa(temperature_c, humidity_ratio) - 10.0)
+}
+
+/// NWS heat index in °C: Steadman's simple formula, or the Rothfusz
+/// regression with its adjustments above 80°F.
+pub(crate) fn heat_index_celsius(temperature_c: f64, humidity_ratio: f64) -> f64 {
+    let t = temperature_c * 9.0 / 5.0 + 32.0;
+    let rh = humidity_ratio * 100.0;
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let heat_index = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.04901523 * t + 10.14333127 * rh
+            - 0.22475541 * t * rh
+            - 0.00683783 * t * t
+            - 0.05481717 * rh * rh
+            + 0.00122874 * t * t * rh
+            + 0.00085282 * t * rh * rh
+            - 0.00000199 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+        }
+        hi
+    };
+    (heat_index - 32.0) * 5.0 / 9.0
+}
+
+/// Density of moist air in kg/m³.
+pub(crate) fn air_density_kg_m3(temperature_c: f64, humidity_ratio: f64, pressure_hpa: f64) -> f64 {
+    let temperature_k = temperature_c + KELVIN;
+    let vapour_pressure_pa = vapour_pressure_hpa(temperature_c, humidity_ratio) * 100.0;
+    let dry_pressure_pa = pressure_hpa * 100.0 - vapour_pressure_pa;
+    dry_pressure_pa / (R_DRY_AIR * temperature_k)
+        + vapour_pressure_pa / (R_WATER_VAPOUR * temperature_k)
+}
+
+/// Station pressure reduced to sea level with the barometric formula,
+/// using the measured temperature instead of the standard atmosphere.
+pub(crate) fn sea_level_pressure_hpa(
+    pressure_hpa: f64,
+    temperature_c: f64,
+    altitude_m: f64,
+) -> f64 {
+    let lapse = LAPSE_RATE * altitude_m;
+    pressure_hpa * (1.0 - lapse / (temperature_c + lapse + KELVIN)).powf(-BAROMETRIC_EXPONENT)
+}
+
+/// Air density is only exported by sensors which also measure pressure.
+pub(crate) fn apply_derived_metrics(
+    metrics: &Metrics,
+    addr: &str,
+    temperature: f64,
+    humidity: f64,
+    pressure_hpa: Option<f64>,
+    derived: &DerivedMetrics,
+) {
+    if derived.absolute_humidity {
+        metrics.set_absolute_humidity(addr, absolute_humidity_g_m3(temperature, humidity));
+    }
+    if derived.vapour_pressure_deficit {
+        metrics
+            .set_vapour_pressure_deficit(addr, vapour_pressure_deficit_kpa(temperature, humidity));
+    }
+    if derived.humidex {
+        metrics.set_humidex(addr, humidex(temperature, humidity));
+    }
+    if derived.heat_index {
+        metrics.set_heat_index(addr, heat_index_celsius(temperature, humidity));
+    }
+    if derived.air_density
+        && let Some(pressure_hpa) = pressure_hpa
+    {
+        metrics.set_air_density(addr, air_density_kg_m3(temperature, humidity, pressure_hpa));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_humidity_matches_reference_table() {
+        // (°C, RH, g/m³)
+        for (temperature, humidity, expected) in
+            [(0.0, 1.0, 4.85), (20.0, 0.5, 8.65), (30.0, 0.8, 24.3)]
+        {
+            let value = absolute_humidity_g_m3(temperature, humidity);
+            assert!(
+                (value - expected).abs() < 0.1,
+                "{}°C {}: {}",
+                temperature,
+                humidity,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn vapour_pressure_deficit_matches_reference_table() {
+        // (°C, RH, kPa)
+        for (temperature, humidity, expected) in
+            [(20.0, 0.5, 1.17), (25.0, 0.6, 1.27), (30.0, 1.0, 0.0)]
+        {
+            let value = vapour_pressure_deficit_kpa(temperature, humidity);
+            assert!((value - expected).abs() < 0.01, "{}", value);
+        }
+    }
+
+    #[test]
+    fn humidex_matches_reference_table() {
+        // Environment Canada humidex table
+        for (temperature, humidity, expected) in [(30.0, 0.7, 41.0), (25.0, 0.5, 28.0)] {
+            let value = humidex(temperature, humidity);
+            assert!((value - expected).abs() < 0.6, "{}", value);
+        }
+    }
+
+    #[test]
+    fn heat_index_matches_reference_table() {
+        // NWS heat index chart, in °F
+        for (fahrenheit, humidity, expected) in
+            [(90.0, 0.7, 106.0), (100.0, 0.4, 109.0), (80.0, 0.4, 80.0)]
+        {
+            let temperature = (fahrenheit - 32.0) * 5.0 / 9.0;
+            let value = heat_index_celsius(temperature, humidity) * 9.0 / 5.0 + 32.0;
+            assert!((value - expected).abs() < 1.0, "{}", value);
+        }
+    }
+
+    #[test]
+    fn air_density_matches_standard_atmosphere() {
+        assert!((air_density_kg_m3(15.0, 0.0, 1013.25) - 1.225).abs() < 0.001);
+        // humid air is lighter than dry air
+        assert!(air_density_kg_m3(30.0, 0.9, 1013.25) < air_density_kg_m3(30.0, 0.0, 1013.25));
+    }
+
+    #[test]
+    fn sea_level_pressure_matches_reference_table() {
+        // (hPa, °C, m, hPa)
+        for (pressure, temperature, altitude, expected) in [
+            (1013.25, 15.0, 0.0, 1013.25),
+            (1000.0, 15.0, 100.0, 1011.92),
+            (950.0, 20.0, 540.0, 1011.34),
+        ] {
+            let value = sea_level_pressure_hpa(pressure, temperature, altitude);
+            assert!((value - expected).abs() < 0.01, "{}", value);
+        }
+    }
+
+    #[test]
+    fn derived_metrics_are_parsed() {
+        assert_eq!(DerivedMetrics::default(), DerivedMetrics::parse(""));
+        assert_eq!(
+            DerivedMetrics {
+                vapour_pressure_deficit: true,
+                air_density: true,
+                ..Default::default()
+            },
+            DerivedMetrics::parse("vpd, air_density")
+        );
+    }
+}
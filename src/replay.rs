@@ -0,0 +1,395 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::capture::CaptureRecord;
+use crate::source::{Advertisement, AdvertisementSender, AdvertisementSource, decode_hex};
+
+const BTSNOOP_MAGIC: &[u8; 8] = b"btsnoop\0";
+const BTSNOOP_HEADER_LEN: usize = 16;
+const BTSNOOP_RECORD_HEADER_LEN: usize = 24;
+/// Microseconds between 0000-01-01 and the unix epoch, btsnoop timestamps start at year 0.
+const BTSNOOP_EPOCH_DELTA_US: i64 = 0x00dc_ddb3_0f2f_8000;
+
+const DATALINK_HCI_UNENCAPSULATED: u32 = 1001;
+const DATALINK_HCI_UART: u32 = 1002;
+const DATALINK_LINUX_MONITOR: u32 = 2001;
+
+const H4_EVENT_PACKET: u8 = 0x04;
+const MONITOR_OPCODE_EVENT_PACKET: u32 = 0x0003;
+const HCI_EVENT_LE_META: u8 = 0x3E;
+const LE_ADVERTISING_REPORT: u8 = 0x02;
+const LE_EXTENDED_ADVERTISING_REPORT: u8 = 0x0D;
+const RSSI_NOT_AVAILABLE: i8 = 127;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn timestamp_to_system_time(timestamp: f64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(timestamp.max(0.0))
+}
+
+fn advertisement_from_record(record: CaptureRecord) -> io::Result<Advertisement> {
+    let data = decode_hex(&record.data).ok_or_else(|| invalid_data("invalid hex in capture"))?;
+    let mut advertisement = Advertisement::new(record.address);
+    advertisement.adapter = record.adapter;
+    advertisement.gateway = record.gateway;
+    advertisement.rssi = record.rssi;
+    advertisement.received_at = timestamp_to_system_time(record.timestamp);
+    advertisement
+        .manufacturer_data
+        .insert(record.company_id, data);
+    Ok(advertisement)
+}
+
+/// Reads a JSONL capture as written by [`crate::capture::CaptureWriter`].
+pub(crate) fn parse_jsonl<R: BufRead>(reader: R) -> io::Result<Vec<Advertisement>> {
+    let mut advertisements = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: CaptureRecord = serde_json::from_str(&line)?;
+        advertisements.push(advertisement_from_record(record)?);
+    }
+    Ok(advertisements)
+}
+
+fn format_reversed_address(address: &[u8]) -> String {
+    address
+        .iter()
+        .rev()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn report_advertisement(
+    address: &[u8],
+    rssi: i8,
+    data: &[u8],
+    received_at: SystemTime,
+) -> Advertisement {
+    let mut advertisement =
+        Advertisement::new(format_reversed_address(address)).with_raw_data(data);
+    advertisement.rssi = (rssi != RSSI_NOT_AVAILABLE).then_some(i16::from(rssi));
+    advertisement.received_at = received_at;
+    advertisement
+}
+
+/// Extracts the advertising reports of an HCI LE Meta event (without packet type).
+fn parse_le_meta_event(event: &[u8], received_at: SystemTime) -> Vec<Advertisement> {
+    let mut advertisements = Vec::new();
+    let [HCI_EVENT_LE_META, _len, subevent, num_reports, reports @ ..] = event else {
+        return advertisements;
+    };
+    let mut rest = reports;
+    for _ in 0..*num_reports {
+        match *subevent {
+            LE_ADVERTISING_REPORT => {
+                // event type, address type, address, data length, data, rssi
+                let [_event_type, _address_type, tail @ ..] = rest else {
+                    break;
+                };
+                let Some((address, tail)) = tail.split_at_checked(6) else {
+                    break;
+                };
+                let Some((&len, tail)) = tail.split_first() else {
+                    break;
+                };
+                let Some((data, tail)) = tail.split_at_checked(usize::from(len)) else {
+                    break;
+                };
+                let Some((&rssi, tail)) = tail.split_first() else {
+                    break;
+                };
+                advertisements.push(report_advertisement(address, rssi as i8, data, received_at));
+                rest = tail;
+            }
+            LE_EXTENDED_ADVERTISING_REPORT => {
+                // event type (2), address type, address, primary/secondary phy, sid,
+                // tx power, rssi, periodic interval (2), direct address type and address
+                let Some((header, tail)) = rest.split_at_checked(23) else {
+                    break;
+                };
+                let Some((&len, tail)) = tail.split_first() else {
+                    break;
+                };
+                let Some((data, tail)) = tail.split_at_checked(usize::from(len)) else {
+                    break;
+                };
+                advertisements.push(report_advertisement(
+                    &header[3..9],
+                    header[13] as i8,
+                    data,
+                    received_at,
+                ));
+                rest = tail;
+            }
+            _ => break,
+        }
+    }
+    advertisements
+}
+
+/// Reads advertising reports from a btsnoop file, as written by `btmon -w` or Android.
+pub(crate) fn parse_btsnoop(bytes: &[u8]) -> io::Result<Vec<Advertisement>> {
+    if bytes.len() < BTSNOOP_HEADER_LEN || &bytes[..8] != BTSNOOP_MAGIC {
+        return Err(invalid_data("not a btsnoop file"));
+    }
+    let datalink = u32::from_be_bytes(bytes[12..16].try_into().unwrap());
+    if ![
+        DATALINK_HCI_UNENCAPSULATED,
+        DATALINK_HCI_UART,
+        DATALINK_LINUX_MONITOR,
+    ]
+    .contains(&datalink)
+    {
+        return Err(invalid_data("unsupported btsnoop datalink type"));
+    }
+
+    let mut advertisements = Vec::new();
+    let mut rest = &bytes[BTSNOOP_HEADER_LEN..];
+    while rest.len() >= BTSNOOP_RECORD_HEADER_LEN {
+        let (header, tail) = rest.split_at(BTSNOOP_RECORD_HEADER_LEN);
+        let included_len = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        let flags = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        let timestamp_us = i64::from_be_bytes(header[16..24].try_into().unwrap());
+        let Some((packet, tail)) = tail.split_at_checked(included_len) else {
+            return Err(invalid_data("truncated btsnoop record"));
+        };
+        rest = tail;
+
+        let event = match datalink {
+            DATALINK_HCI_UART => match packet.split_first() {
+                Some((&H4_EVENT_PACKET, event)) => event,
+                _ => continue,
+            },
+            DATALINK_HCI_UNENCAPSULATED if flags & 0b11 == 0b11 => packet,
+            DATALINK_LINUX_MONITOR if flags & 0xFFFF == MONITOR_OPCODE_EVENT_PACKET => packet,
+            _ => continue,
+        };
+        let micros = timestamp_us.saturating_sub(BTSNOOP_EPOCH_DELTA_US).max(0) as u64;
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_micros(micros);
+        advertisements.extend(parse_le_meta_event(event, received_at));
+    }
+    Ok(advertisements)
+}
+
+/// Reads a capture file, detecting btsnoop files by their magic and treating
+/// everything else as JSONL.
+pub(crate) fn read_capture(path: &Path) -> io::Result<Vec<Advertisement>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(BTSNOOP_MAGIC) {
+        parse_btsnoop(&bytes)
+    } else {
+        parse_jsonl(bytes.as_slice())
+    }
+}
+
+/// Time to wait before replaying `next` to keep the original pacing.
+fn replay_delay(previous: SystemTime, next: SystemTime) -> Duration {
+    next.duration_since(previous).unwrap_or_default()
+}
+
+/// Replays a recorded capture, either at the original pacing or as fast as possible.
+pub(crate) struct ReplaySource {
+    path: PathBuf,
+    realtime: bool,
+}
+
+impl ReplaySource {
+    pub(crate) fn new(path: impl Into<PathBuf>, realtime: bool) -> Self {
+        Self {
+            path: path.into(),
+            realtime,
+        }
+    }
+}
+
+impl AdvertisementSource for ReplaySource {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    async fn run(self, sink: AdvertisementSender) -> bluer::Result<()> {
+        let advertisements = read_capture(&self.path)?;
+        println!(
+            "Replaying {} advertisements from {}",
+            advertisements.len(),
+            self.path.display()
+        );
+        let mut previous: Option<SystemTime> = None;
+        for advertisement in advertisements {
+            if self.realtime
+                && let Some(previous) = previous
+            {
+                tokio::time::sleep(replay_delay(previous, advertisement.received_at)).await;
+            }
+            previous = Some(advertisement.received_at);
+            if sink.send(advertisement).await.is_err() {
+                break;
+            }
+        }
+        println!("Replay of {} finished", self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::ruuvi::Decoder;
+    use crate::source::{RUUVI_COMPANY_ID, process_advertisements, spawn_source};
+    use crate::test_utils::metrics::{clear, counter_value, gauge_value, take_snapshot};
+    use tokio::sync::mpsc;
+
+    const PAYLOAD: [u8; 24] = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
+
+    const CAPTURE: &str = r#"{"timestamp":1700000000.5,"adapter":"hci0","address":"aa:bb","rssi":-61,"company_id":1177,"data":"0512fc5394c37c0004fffc040cac364200cdcbb8334c884f"}
+
+{"timestamp":1700000002.0,"address":"aa:bb","rssi":null,"company_id":1177,"data":"0512fc5394c37c0004fffc040cac364200cdcbb8334c884f"}
+"#;
+
+    fn btsnoop_file(datalink: u32, flags: u32, packet: &[u8]) -> Vec<u8> {
+        let mut bytes = BTSNOOP_MAGIC.to_vec();
+        bytes.extend(1u32.to_be_bytes());
+        bytes.extend(datalink.to_be_bytes());
+        bytes.extend((packet.len() as u32).to_be_bytes());
+        bytes.extend((packet.len() as u32).to_be_bytes());
+        bytes.extend(flags.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend((BTSNOOP_EPOCH_DELTA_US + 1_700_000_000_000_000).to_be_bytes());
+        bytes.extend(packet);
+        bytes
+    }
+
+    fn le_advertising_report() -> Vec<u8> {
+        let mut data = hex_literal::hex!("0201061BFF9904").to_vec();
+        data.extend(PAYLOAD);
+        let mut params = vec![LE_ADVERTISING_REPORT, 1, 0x00, 0x01];
+        params.extend([0x4F, 0x88, 0x4C, 0x33, 0xB8, 0xCB]);
+        params.push(data.len() as u8);
+        params.extend(data);
+        params.push(-70i8 as u8);
+        let mut event = vec![HCI_EVENT_LE_META, params.len() as u8];
+        event.extend(params);
+        event
+    }
+
+    #[test]
+    fn jsonl_capture_is_parsed() {
+        let advertisements = parse_jsonl(CAPTURE.as_bytes()).unwrap();
+
+        assert_eq!(2, advertisements.len());
+        assert_eq!("aa:bb", advertisements[0].address);
+        assert_eq!(Some("hci0"), advertisements[0].adapter.as_deref());
+        assert_eq!(Some(-61), advertisements[0].rssi);
+        assert_eq!(None, advertisements[1].rssi);
+        assert_eq!(
+            Some(&PAYLOAD.to_vec()),
+            advertisements[0].manufacturer_data.get(&RUUVI_COMPANY_ID)
+        );
+        assert_eq!(
+            Duration::from_millis(1500),
+            replay_delay(advertisements[0].received_at, advertisements[1].received_at)
+        );
+        assert_eq!(
+            Duration::ZERO,
+            replay_delay(advertisements[1].received_at, advertisements[0].received_at)
+        );
+    }
+
+    #[test]
+    fn invalid_jsonl_capture_is_rejected() {
+        assert!(parse_jsonl("{\"timestamp\": 1}".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn btsnoop_h4_advertising_report_is_parsed() {
+        let mut packet = vec![H4_EVENT_PACKET];
+        packet.extend(le_advertising_report());
+
+        let advertisements = parse_btsnoop(&btsnoop_file(DATALINK_HCI_UART, 1, &packet)).unwrap();
+
+        assert_eq!(1, advertisements.len());
+        let advertisement = &advertisements[0];
+        assert_eq!("cb:b8:33:4c:88:4f", advertisement.address);
+        assert_eq!(Some(-70), advertisement.rssi);
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            advertisement.received_at
+        );
+        assert_eq!(
+            Some(&PAYLOAD.to_vec()),
+            advertisement.manufacturer_data.get(&RUUVI_COMPANY_ID)
+        );
+    }
+
+    #[test]
+    fn btsnoop_monitor_skips_non_event_packets() {
+        let report = le_advertising_report();
+
+        let events = parse_btsnoop(&btsnoop_file(
+            DATALINK_LINUX_MONITOR,
+            MONITOR_OPCODE_EVENT_PACKET,
+            &report,
+        ))
+        .unwrap();
+        let commands =
+            parse_btsnoop(&btsnoop_file(DATALINK_LINUX_MONITOR, 0x0002, &report)).unwrap();
+
+        assert_eq!(1, events.len());
+        assert!(commands.is_empty());
+        assert!(parse_btsnoop(b"not a btsnoop file").is_err());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn replay_feeds_metrics_pipeline() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let path = std::env::temp_dir().join(format!(
+            "ruuvi-prometheus-rs-replay-{}.jsonl",
+            std::process::id()
+        ));
+        fs::write(&path, CAPTURE).unwrap();
+
+        let (sink, advertisements) = mpsc::channel(8);
+        spawn_source(ReplaySource::new(&path, false), sink);
+        process_advertisements(advertisements, metrics, Decoder::default(), None).await;
+        fs::remove_file(&path).unwrap();
+
+        let snapshot = take_snapshot();
+        // the second line repeats the sequence number of the first one
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", "aa:bb"), ("format", "5")]
+            )
+        );
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_duplicate_total",
+                &[("device", "aa:bb"), ("format", "5")]
+            )
+        );
+        assert!(
+            gauge_value(
+                &snapshot,
+                "ruuvi_rssi_dbm",
+                &[("device", "aa:bb"), ("adapter", "hci0")]
+            )
+            .is_some_and(|v| (v + 61.0).abs() < f64::EPSILON)
+        );
+    }
+}
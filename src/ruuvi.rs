@@ -1,7 +1,20 @@
-use std::time::SystemTime;
+use std::collections::BTreeMap;
+use std::time::{Instant, SystemTime};
 
+use crate::battery::BatteryModel;
+use crate::bthome::{self, BthomeData, VENDOR_BTHOME, apply_bthome_metrics};
+use crate::calibration::Calibration;
+use crate::filter::DeviceFilter;
+use crate::formats::{
+    self, DataFormat3, DataFormat8, DataFormatC5, EncryptionKey, FORMAT_C5, FORMAT_V3, FORMAT_V8,
+};
+use crate::liveness::DeviceLiveness;
 use crate::metrics::Metrics;
-use ruuvi_decoders::{self, RuuviData};
+use crate::orientation::{self, OrientationTracker};
+use crate::psychrometrics::{DerivedMetrics, apply_derived_metrics, sea_level_pressure_hpa};
+use crate::sequence::{MovementTracker, SequenceStep, SequenceTracker};
+use crate::xiaomi::{self, VENDOR_XIAOMI, XiaomiData, apply_xiaomi_metrics};
+use ruuvi_decoders::{self, DecodeError, RuuviData};
 
 pub(crate) struct EnvironmentReadings {
     pub temperature: f64,
@@ -41,7 +54,21 @@ pub(crate) trait HasAirQuality {
     fn air_quality(&self) -> Option<AirQualityReadings>;
 }
 
+pub(crate) struct LightSoundReadings {
+    pub illuminance_lux: Option<f64>,
+    pub sound_instant_dba: Option<f64>,
+    pub sound_average_dba: Option<f64>,
+    pub sound_peak_dba: Option<f64>,
+}
+
+pub(crate) trait HasLightSound {
+    fn light_sound(&self) -> Option<LightSoundReadings>;
+}
+
 pub(crate) trait HasSequenceNumber {
+    /// Number of distinct values of the counter before it wraps around.
+    const SEQUENCE_MODULUS: u32;
+
     fn sequence_number(&self) -> Option<f64>;
 }
 
@@ -49,18 +76,63 @@ pub(crate) fn apply_environment_metrics<T: HasEnvironment>(
     metrics: &Metrics,
     addr: &str,
     data: &T,
+    calibration: &Calibration,
+    derived: &DerivedMetrics,
+    altitude: Option<f64>,
 ) {
-    if let Some(env) = data.environment() {
+    if let Some(env) = data.environment().map(|env| calibration.environment(env)) {
         metrics.set_temperature(addr, env.temperature);
         metrics.set_humidity(addr, env.humidity_ratio);
         if let Some(dew_point) = dew_point_celsius(env.temperature, env.humidity_ratio) {
             metrics.set_dew_point(addr, dew_point);
         }
         metrics.set_pressure(addr, env.pressure_hpa);
+        if let Some(altitude) = altitude {
+            metrics.set_pressure_sea_level(
+                addr,
+                sea_level_pressure_hpa(env.pressure_hpa, env.temperature, altitude),
+            );
+        }
+        apply_derived_metrics(
+            metrics,
+            addr,
+            env.temperature,
+            env.humidity_ratio,
+            Some(env.pressure_hpa),
+            derived,
+        );
+    }
+}
+
+/// The battery percentage is estimated from the voltage at the tag's own
+/// (calibrated) temperature.
+pub(crate) fn apply_battery_metrics<T: HasEnvironment + HasMotion>(
+    metrics: &Metrics,
+    addr: &str,
+    data: &T,
+    calibration: &Calibration,
+    battery: &BatteryModel,
+) {
+    if let (Some(env), Some(volts)) = (
+        data.environment().map(|env| calibration.environment(env)),
+        data.motion().and_then(|motion| motion.battery_voltage),
+    ) {
+        let percent = battery.percent(volts, env.temperature);
+        metrics.set_battery_percent(addr, percent);
+        metrics.set_battery_low(addr, battery.is_low(percent));
     }
 }
 
-pub(crate) fn apply_motion_metrics<T: HasMotion>(metrics: &Metrics, addr: &str, data: &T) {
+/// The movement counter is a `u8`, with 255 meaning "not available".
+const MOVEMENT_COUNTER_MODULUS: u32 = u8::MAX as u32;
+
+pub(crate) fn apply_motion_metrics<T: HasMotion>(
+    metrics: &Metrics,
+    addr: &str,
+    data: &T,
+    movements: &mut MovementTracker,
+    orientation: &mut OrientationTracker,
+) {
     if let Some(motion) = data.motion() {
         if let Some(acceleration_x) = motion.acceleration_x_g {
             metrics.set_acceleration(addr, "X", acceleration_x);
@@ -71,6 +143,18 @@ pub(crate) fn apply_motion_metrics<T: HasMotion>(metrics: &Metrics, addr: &str,
         if let Some(acceleration_z) = motion.acceleration_z_g {
             metrics.set_acceleration(addr, "Z", acceleration_z);
         }
+        if let (Some(x), Some(y), Some(z)) = (
+            motion.acceleration_x_g,
+            motion.acceleration_y_g,
+            motion.acceleration_z_g,
+        ) {
+            let acceleration = [x, y, z];
+            metrics.set_acceleration_magnitude(addr, orientation::magnitude(&acceleration));
+            metrics.set_pitch(addr, orientation::pitch_degrees(&acceleration));
+            metrics.set_roll(addr, orientation::roll_degrees(&acceleration));
+            let changed = orientation.observe(addr, acceleration);
+            metrics.inc_orientation_changes(addr, changed.into());
+        }
         if let Some(voltage) = motion.battery_voltage {
             metrics.set_voltage(addr, voltage);
         }
@@ -79,6 +163,8 @@ pub(crate) fn apply_motion_metrics<T: HasMotion>(metrics: &Metrics, addr: &str,
         }
         if let Some(movement_count) = motion.movement_count {
             metrics.set_move_count(addr, movement_count);
+            let detected = movements.observe(addr, movement_count as u32, MOVEMENT_COUNTER_MODULUS);
+            metrics.inc_movements(addr, detected.into());
         }
     }
 }
@@ -91,8 +177,13 @@ const CO2_MAX: f64 = 2300.;
 const CO2_MIN: f64 = 420.;
 const CO2_SCALE: f64 = AQI_MAX / (CO2_MAX - CO2_MIN); // ≈ 0.05319
 
-pub(crate) fn apply_air_quality_metrics<T: HasAirQuality>(metrics: &Metrics, addr: &str, data: &T) {
-    if let Some(air) = data.air_quality() {
+pub(crate) fn apply_air_quality_metrics<T: HasAirQuality>(
+    metrics: &Metrics,
+    addr: &str,
+    data: &T,
+    calibration: &Calibration,
+) {
+    if let Some(air) = data.air_quality().map(|air| calibration.air_quality(air)) {
         if let Some(pm1_0) = air.pm1_0 {
             metrics.set_pm1_0(addr, pm1_0);
         }
@@ -137,48 +228,448 @@ pub(crate) fn apply_air_quality_metrics<T: HasAirQuality>(metrics: &Metrics, add
     }
 }
 
+pub(crate) fn apply_light_sound_metrics<T: HasLightSound>(metrics: &Metrics, addr: &str, data: &T) {
+    if let Some(light_sound) = data.light_sound() {
+        if let Some(illuminance) = light_sound.illuminance_lux {
+            metrics.set_illuminance(addr, illuminance);
+        }
+        if let Some(instant) = light_sound.sound_instant_dba {
+            metrics.set_sound(addr, "instant", instant);
+        }
+        if let Some(average) = light_sound.sound_average_dba {
+            metrics.set_sound(addr, "average", average);
+        }
+        if let Some(peak) = light_sound.sound_peak_dba {
+            metrics.set_sound(addr, "peak", peak);
+        }
+    }
+}
+
 pub(crate) fn apply_sequence_number<T: HasSequenceNumber>(metrics: &Metrics, addr: &str, data: &T) {
     if let Some(seqno) = data.sequence_number() {
         metrics.set_seqno(addr, seqno);
     }
 }
 
-pub(crate) fn handle_manufacturer_data(metrics: &Metrics, addr: &str, value: &[u8]) {
-    let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
-    match ruuvi_decoders::decode(hex.as_str()) {
-        Ok(data) => {
-            #[cfg(debug_assertions)]
-            println!("{:?}", data);
+enum Frame {
+    Ruuvi(RuuviData),
+    V3(DataFormat3),
+    V8(DataFormat8),
+    C5(DataFormatC5),
+}
+
+/// The `reason` label of `ruuvi_decryption_errors_total` for a format 8 frame that failed to decode.
+fn decryption_error_reason(err: &DecodeError) -> &'static str {
+    match err {
+        DecodeError::DecryptionFailed(_) => "crc",
+        DecodeError::InvalidLength(_) => "length",
+        _ => "invalid",
+    }
+}
+
+fn decode_frame(value: &[u8], key: Option<&EncryptionKey>) -> Result<Frame, DecodeError> {
+    match (value.first(), key) {
+        (Some(&FORMAT_V3), _) => formats::decode_v3(value).map(Frame::V3),
+        (Some(&FORMAT_V8), Some(key)) => formats::decode_v8(value, key).map(Frame::V8),
+        (Some(&FORMAT_C5), _) => formats::decode_c5(value).map(Frame::C5),
+        _ => {
+            let hex: String = value.iter().map(|b| format!("{:02x}", b)).collect();
+            ruuvi_decoders::decode(hex.as_str()).map(Frame::Ruuvi)
+        }
+    }
+}
+
+/// Decodes manufacturer data, holding the per-device configuration needed
+/// for that. Owned by the single consumer of the advertisement queue.
+#[derive(Debug, Default)]
+pub(crate) struct Decoder {
+    encryption_keys: BTreeMap<String, EncryptionKey>,
+    filter: DeviceFilter,
+    calibrations: BTreeMap<String, Calibration>,
+    derived_metrics: DerivedMetrics,
+    altitudes: BTreeMap<String, f64>,
+    default_altitude: Option<f64>,
+    sequences: SequenceTracker,
+    movements: MovementTracker,
+    orientation: OrientationTracker,
+    battery: BatteryModel,
+    liveness: DeviceLiveness,
+}
+
+impl Decoder {
+    pub(crate) fn new(encryption_keys: BTreeMap<String, EncryptionKey>) -> Self {
+        Self {
+            encryption_keys,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn with_filter(self, filter: DeviceFilter) -> Self {
+        Self { filter, ..self }
+    }
+
+    pub(crate) fn with_calibrations(self, calibrations: BTreeMap<String, Calibration>) -> Self {
+        Self {
+            calibrations,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_derived_metrics(self, derived_metrics: DerivedMetrics) -> Self {
+        Self {
+            derived_metrics,
+            ..self
+        }
+    }
+
+    /// Altitude per device, falling back to the global altitude.
+    pub(crate) fn with_altitudes(
+        self,
+        altitudes: BTreeMap<String, f64>,
+        default_altitude: Option<f64>,
+    ) -> Self {
+        Self {
+            altitudes,
+            default_altitude,
+            ..self
+        }
+    }
+
+    pub(crate) fn with_orientation_threshold(self, threshold_degrees: f64) -> Self {
+        Self {
+            orientation: OrientationTracker::new(threshold_degrees),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_battery_model(self, battery: BatteryModel) -> Self {
+        Self { battery, ..self }
+    }
+
+    pub(crate) fn with_liveness(self, liveness: DeviceLiveness) -> Self {
+        Self { liveness, ..self }
+    }
+
+    pub(crate) fn filter(&self) -> &DeviceFilter {
+        &self.filter
+    }
+
+    pub(crate) fn liveness(&self) -> &DeviceLiveness {
+        &self.liveness
+    }
+
+    fn altitude(&self, addr: &str) -> Option<f64> {
+        self.altitudes.get(addr).copied().or(self.default_altitude)
+    }
+
+    /// Counts frames missed since the previous one of the device. Returns true
+    /// for repeated frames, whose metrics should not be updated again.
+    fn is_duplicate<T: HasSequenceNumber>(
+        &mut self,
+        metrics: &Metrics,
+        addr: &str,
+        format: &'static str,
+        data: &T,
+    ) -> bool {
+        let Some(seqno) = data.sequence_number() else {
+            return false;
+        };
+        match self.sequences.observe(
+            addr,
+            format,
+            seqno as u32,
+            T::SEQUENCE_MODULUS,
+            Instant::now(),
+        ) {
+            SequenceStep::Duplicate | SequenceStep::Late => {
+                metrics.inc_frames_duplicate(addr, format);
+                true
+            }
+            SequenceStep::Advanced { missed } if missed > 0 => {
+                metrics.inc_frames_missed(addr, format, missed.into());
+                false
+            }
+            SequenceStep::Restarted => {
+                // a reboot of the tag also resets its movement counter
+                self.movements.reset(addr);
+                false
+            }
+            SequenceStep::Advanced { .. } => false,
+        }
+    }
+
+    fn calibration(&self, addr: &str) -> Calibration {
+        self.calibrations
+            .get(addr)
+            .copied()
+            .unwrap_or(Calibration::IDENTITY)
+    }
+
+    pub(crate) fn handle_manufacturer_data(&mut self, metrics: &Metrics, addr: &str, value: &[u8]) {
+        let key = self.encryption_keys.get(addr);
+        let calibration = self.calibration(addr);
+        let altitude = self.altitude(addr);
+        if value.first() == Some(&FORMAT_V8) && key.is_none() {
+            metrics.inc_decryption_errors(addr, "no_key");
+            return;
+        }
+        match decode_frame(value, key) {
+            Ok(frame) => {
+                match frame {
+                    Frame::Ruuvi(data) => {
+                        #[cfg(debug_assertions)]
+                        println!("{:?}", data);
+
+                        match data {
+                            RuuviData::V5(v5) => {
+                                if self.is_duplicate(metrics, addr, "5", &v5) {
+                                    return;
+                                }
+                                metrics.inc_ruuvi_frames(addr, "5");
+                                apply_environment_metrics(
+                                    metrics,
+                                    addr,
+                                    &v5,
+                                    &calibration,
+                                    &self.derived_metrics,
+                                    altitude,
+                                );
+                                apply_motion_metrics(
+                                    metrics,
+                                    addr,
+                                    &v5,
+                                    &mut self.movements,
+                                    &mut self.orientation,
+                                );
+                                apply_battery_metrics(
+                                    metrics,
+                                    addr,
+                                    &v5,
+                                    &calibration,
+                                    &self.battery,
+                                );
+                                apply_sequence_number(metrics, addr, &v5);
+                            }
+                            RuuviData::V6(v6) => {
+                                if self.is_duplicate(metrics, addr, "6", &v6) {
+                                    return;
+                                }
+                                metrics.inc_ruuvi_frames(addr, "6");
+                                apply_environment_metrics(
+                                    metrics,
+                                    addr,
+                                    &v6,
+                                    &calibration,
+                                    &self.derived_metrics,
+                                    altitude,
+                                );
+                                apply_air_quality_metrics(metrics, addr, &v6, &calibration);
+                                apply_light_sound_metrics(metrics, addr, &v6);
+                                apply_sequence_number(metrics, addr, &v6);
+                            }
+                            RuuviData::E1(e1) => {
+                                if self.is_duplicate(metrics, addr, "E1", &e1) {
+                                    return;
+                                }
+                                metrics.inc_ruuvi_frames(addr, "E1");
+                                apply_environment_metrics(
+                                    metrics,
+                                    addr,
+                                    &e1,
+                                    &calibration,
+                                    &self.derived_metrics,
+                                    altitude,
+                                );
+                                apply_air_quality_metrics(metrics, addr, &e1, &calibration);
+                                apply_light_sound_metrics(
+                                    metrics,
+                                    addr,
+                                    &E1Frame {
+                                        data: &e1,
+                                        raw: value,
+                                    },
+                                );
+                                apply_sequence_number(metrics, addr, &e1);
+                            }
+                        }
+                    }
+                    Frame::V3(v3) => {
+                        #[cfg(debug_assertions)]
+                        println!("{:?}", v3);
 
-            match data {
-                RuuviData::V5(v5) => {
-                    metrics.inc_ruuvi_frames(addr, "5");
-                    apply_environment_metrics(metrics, addr, &v5);
-                    apply_motion_metrics(metrics, addr, &v5);
-                    apply_sequence_number(metrics, addr, &v5);
+                        metrics.inc_ruuvi_frames(addr, "3");
+                        apply_environment_metrics(
+                            metrics,
+                            addr,
+                            &v3,
+                            &calibration,
+                            &self.derived_metrics,
+                            altitude,
+                        );
+                        apply_motion_metrics(
+                            metrics,
+                            addr,
+                            &v3,
+                            &mut self.movements,
+                            &mut self.orientation,
+                        );
+                        apply_battery_metrics(metrics, addr, &v3, &calibration, &self.battery);
+                    }
+                    Frame::V8(v8) => {
+                        #[cfg(debug_assertions)]
+                        println!("{:?}", v8);
+
+                        if self.is_duplicate(metrics, addr, "8", &v8) {
+                            return;
+                        }
+                        metrics.inc_ruuvi_frames(addr, "8");
+                        apply_environment_metrics(
+                            metrics,
+                            addr,
+                            &v8,
+                            &calibration,
+                            &self.derived_metrics,
+                            altitude,
+                        );
+                        apply_motion_metrics(
+                            metrics,
+                            addr,
+                            &v8,
+                            &mut self.movements,
+                            &mut self.orientation,
+                        );
+                        apply_battery_metrics(metrics, addr, &v8, &calibration, &self.battery);
+                        apply_sequence_number(metrics, addr, &v8);
+                    }
+                    Frame::C5(c5) => {
+                        #[cfg(debug_assertions)]
+                        println!("{:?}", c5);
+
+                        if self.is_duplicate(metrics, addr, "C5", &c5) {
+                            return;
+                        }
+                        metrics.inc_ruuvi_frames(addr, "C5");
+                        apply_environment_metrics(
+                            metrics,
+                            addr,
+                            &c5,
+                            &calibration,
+                            &self.derived_metrics,
+                            altitude,
+                        );
+                        apply_motion_metrics(
+                            metrics,
+                            addr,
+                            &c5,
+                            &mut self.movements,
+                            &mut self.orientation,
+                        );
+                        apply_battery_metrics(metrics, addr, &c5, &calibration, &self.battery);
+                        apply_sequence_number(metrics, addr, &c5);
+                    }
+                }
+
+                let timestamp = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as f64;
+                metrics.set_last_updated(addr, timestamp);
+            }
+            Err(err) if value.first() == Some(&FORMAT_V8) => {
+                metrics.inc_decryption_errors(addr, decryption_error_reason(&err))
+            }
+            Err(err) => println!("Error decoding data: {}", err),
+        };
+    }
+
+    pub(crate) fn handle_bthome_data(&mut self, metrics: &Metrics, addr: &str, value: &[u8]) {
+        let metrics = metrics.with_vendor(VENDOR_BTHOME);
+        match bthome::decode(value) {
+            Ok(data) => {
+                let data = data.calibrated(&self.calibration(addr));
+                #[cfg(debug_assertions)]
+                println!("{:?}", data);
+
+                if self.is_duplicate(&metrics, addr, "bthome_v2", &data) {
+                    return;
+                }
+                metrics.inc_ruuvi_frames(addr, "bthome_v2");
+                apply_bthome_metrics(&metrics, addr, &data);
+                if let Some(battery) = data.battery {
+                    metrics.set_battery_low(addr, self.battery.is_low(battery));
+                }
+                if data.dew_point.is_none()
+                    && let (Some(temperature), Some(humidity)) = (data.temperature, data.humidity)
+                    && let Some(dew_point) = dew_point_celsius(temperature, humidity / 100.0)
+                {
+                    metrics.set_dew_point(addr, dew_point);
                 }
-                RuuviData::V6(v6) => {
-                    metrics.inc_ruuvi_frames(addr, "6");
-                    apply_environment_metrics(metrics, addr, &v6);
-                    apply_air_quality_metrics(metrics, addr, &v6);
-                    apply_sequence_number(metrics, addr, &v6);
+                if let (Some(pressure), Some(temperature), Some(altitude)) =
+                    (data.pressure, data.temperature, self.altitude(addr))
+                {
+                    metrics.set_pressure_sea_level(
+                        addr,
+                        sea_level_pressure_hpa(pressure, temperature, altitude),
+                    );
                 }
-                RuuviData::E1(e1) => {
-                    metrics.inc_ruuvi_frames(addr, "E1");
-                    apply_environment_metrics(metrics, addr, &e1);
-                    apply_air_quality_metrics(metrics, addr, &e1);
-                    apply_sequence_number(metrics, addr, &e1);
+                if let (Some(temperature), Some(humidity)) = (data.temperature, data.humidity) {
+                    apply_derived_metrics(
+                        &metrics,
+                        addr,
+                        temperature,
+                        humidity / 100.0,
+                        data.pressure,
+                        &self.derived_metrics,
+                    );
                 }
+                let timestamp = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as f64;
+                metrics.set_last_updated(addr, timestamp);
             }
+            Err(err) => println!("Error decoding BTHome data: {}", err),
+        }
+    }
 
-            let timestamp = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as f64;
-            metrics.set_last_updated(addr, timestamp);
+    pub(crate) fn handle_xiaomi_data(&mut self, metrics: &Metrics, addr: &str, value: &[u8]) {
+        let metrics = metrics.with_vendor(VENDOR_XIAOMI);
+        match xiaomi::decode(value) {
+            Some(data) => {
+                let data = data.calibrated(&self.calibration(addr));
+                #[cfg(debug_assertions)]
+                println!("{:?}", data);
+
+                if self.is_duplicate(&metrics, addr, data.format.label(), &data) {
+                    return;
+                }
+                metrics.inc_ruuvi_frames(addr, data.format.label());
+                apply_xiaomi_metrics(&metrics, addr, &data);
+                metrics.set_battery_low(addr, self.battery.is_low(f64::from(data.battery_level)));
+                if let Some(dew_point) = dew_point_celsius(data.temperature, data.humidity / 100.0)
+                {
+                    metrics.set_dew_point(addr, dew_point);
+                }
+                apply_derived_metrics(
+                    &metrics,
+                    addr,
+                    data.temperature,
+                    data.humidity / 100.0,
+                    None,
+                    &self.derived_metrics,
+                );
+                let timestamp = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as f64;
+                metrics.set_last_updated(addr, timestamp);
+            }
+            None => println!(
+                "Error decoding Xiaomi data: unsupported length {}",
+                value.len()
+            ),
         }
-        Err(err) => println!("Error decoding data: {}", err),
-    };
+    }
 }
 
 const DEW_POINT_B: f64 = 17.368;
@@ -229,6 +720,8 @@ impl HasMotion for ruuvi_decoders::v5::DataFormatV5 {
 }
 
 impl HasSequenceNumber for ruuvi_decoders::v5::DataFormatV5 {
+    const SEQUENCE_MODULUS: u32 = u16::MAX as u32;
+
     fn sequence_number(&self) -> Option<f64> {
         self.measurement_sequence.map(f64::from)
     }
@@ -259,7 +752,21 @@ impl HasAirQuality for ruuvi_decoders::v6::DataFormatV6 {
     }
 }
 
+impl HasLightSound for ruuvi_decoders::v6::DataFormatV6 {
+    fn light_sound(&self) -> Option<LightSoundReadings> {
+        // Format 6 only carries luminosity, the sound byte is reserved.
+        Some(LightSoundReadings {
+            illuminance_lux: self.luminosity,
+            sound_instant_dba: None,
+            sound_average_dba: None,
+            sound_peak_dba: None,
+        })
+    }
+}
+
 impl HasSequenceNumber for ruuvi_decoders::v6::DataFormatV6 {
+    const SEQUENCE_MODULUS: u32 = 256;
+
     fn sequence_number(&self) -> Option<f64> {
         self.measurement_sequence.map(f64::from)
     }
@@ -290,15 +797,156 @@ impl HasAirQuality for ruuvi_decoders::e1::DataFormatE1 {
     }
 }
 
+/// `ruuvi_decoders` does not decode the E1 sound levels, so they are read
+/// from the raw frame alongside the decoded data.
+pub(crate) struct E1Frame<'a> {
+    pub data: &'a ruuvi_decoders::e1::DataFormatE1,
+    pub raw: &'a [u8],
+}
+
+const E1_SOUND_OFFSET_DBA: f64 = 18.0;
+const E1_SOUND_DBA_PER_BIT: f64 = 0.2;
+const E1_SOUND_INVALID: u16 = 0x1FF;
+const E1_FLAGS_INDEX: usize = 28;
+
+impl E1Frame<'_> {
+    /// Sound levels are 9 bit values: the upper 8 bits are stored in `index`,
+    /// the lowest bit in `flag_bit` of the flags byte.
+    fn sound_dba(&self, index: usize, flag_bit: u8) -> Option<f64> {
+        let msb = u16::from(*self.raw.get(index)?);
+        let flags = *self.raw.get(E1_FLAGS_INDEX)?;
+        let raw = (msb << 1) | u16::from((flags >> flag_bit) & 0b1);
+        if raw == E1_SOUND_INVALID {
+            return None;
+        }
+        Some(f64::from(raw) * E1_SOUND_DBA_PER_BIT + E1_SOUND_OFFSET_DBA)
+    }
+}
+
+impl HasLightSound for E1Frame<'_> {
+    fn light_sound(&self) -> Option<LightSoundReadings> {
+        Some(LightSoundReadings {
+            illuminance_lux: self.data.luminosity,
+            sound_instant_dba: self.sound_dba(22, 4),
+            sound_average_dba: self.sound_dba(23, 5),
+            sound_peak_dba: self.sound_dba(24, 3),
+        })
+    }
+}
+
 impl HasSequenceNumber for ruuvi_decoders::e1::DataFormatE1 {
+    const SEQUENCE_MODULUS: u32 = 0xFF_FFFF;
+
+    fn sequence_number(&self) -> Option<f64> {
+        self.measurement_sequence.map(f64::from)
+    }
+}
+
+impl HasEnvironment for DataFormat3 {
+    fn environment(&self) -> Option<EnvironmentReadings> {
+        Some(EnvironmentReadings {
+            temperature: self.temperature,
+            humidity_ratio: self.humidity / 100.0,
+            pressure_hpa: self.pressure / 100.0,
+        })
+    }
+}
+
+impl HasMotion for DataFormat3 {
+    fn motion(&self) -> Option<MotionReadings> {
+        Some(MotionReadings {
+            acceleration_x_g: Some(f64::from(self.acceleration_x) / 1000.0),
+            acceleration_y_g: Some(f64::from(self.acceleration_y) / 1000.0),
+            acceleration_z_g: Some(f64::from(self.acceleration_z) / 1000.0),
+            battery_voltage: Some(f64::from(self.battery_voltage) / 1000.0),
+            tx_power: None,
+            movement_count: None,
+        })
+    }
+}
+
+impl HasEnvironment for DataFormatC5 {
+    fn environment(&self) -> Option<EnvironmentReadings> {
+        Some(EnvironmentReadings {
+            temperature: self.temperature?,
+            humidity_ratio: self.humidity? / 100.0,
+            pressure_hpa: self.pressure? / 100.0,
+        })
+    }
+}
+
+impl HasMotion for DataFormatC5 {
+    fn motion(&self) -> Option<MotionReadings> {
+        Some(MotionReadings {
+            acceleration_x_g: None,
+            acceleration_y_g: None,
+            acceleration_z_g: None,
+            battery_voltage: self.battery_voltage.map(|v| f64::from(v) / 1000.0),
+            tx_power: self.tx_power.map(f64::from),
+            movement_count: self.movement_counter.map(f64::from),
+        })
+    }
+}
+
+impl HasSequenceNumber for DataFormatC5 {
+    const SEQUENCE_MODULUS: u32 = u16::MAX as u32;
+
+    fn sequence_number(&self) -> Option<f64> {
+        self.measurement_sequence.map(f64::from)
+    }
+}
+
+impl HasEnvironment for DataFormat8 {
+    fn environment(&self) -> Option<EnvironmentReadings> {
+        Some(EnvironmentReadings {
+            temperature: self.temperature?,
+            humidity_ratio: self.humidity? / 100.0,
+            pressure_hpa: self.pressure? / 100.0,
+        })
+    }
+}
+
+impl HasMotion for DataFormat8 {
+    fn motion(&self) -> Option<MotionReadings> {
+        Some(MotionReadings {
+            acceleration_x_g: None,
+            acceleration_y_g: None,
+            acceleration_z_g: None,
+            battery_voltage: self.battery_voltage.map(|v| f64::from(v) / 1000.0),
+            tx_power: self.tx_power.map(f64::from),
+            movement_count: self.movement_counter.map(f64::from),
+        })
+    }
+}
+
+impl HasSequenceNumber for DataFormat8 {
+    const SEQUENCE_MODULUS: u32 = u16::MAX as u32;
+
     fn sequence_number(&self) -> Option<f64> {
         self.measurement_sequence.map(f64::from)
     }
 }
 
+impl HasSequenceNumber for BthomeData {
+    const SEQUENCE_MODULUS: u32 = 256;
+
+    fn sequence_number(&self) -> Option<f64> {
+        self.packet_id.map(f64::from)
+    }
+}
+
+impl HasSequenceNumber for XiaomiData {
+    const SEQUENCE_MODULUS: u32 = 256;
+
+    fn sequence_number(&self) -> Option<f64> {
+        Some(f64::from(self.frame_counter))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::calibration::Correction;
     use crate::test_utils::metrics::{clear, counter_value, gauge_value, take_snapshot};
 
     #[test]
@@ -347,6 +995,28 @@ mod tests {
         assert_eq!(Some(205.0), payload.sequence_number());
     }
 
+    #[test]
+    fn uncalibrated_humidity_above_saturation_is_exported_without_dew_point() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "aa:bb:cc:dd:ee:05";
+        // 0xA410 is 105% RH
+        let payload = hex_literal::hex!("0512FCA410C37C0004FFFC040CAC364200CDCBB8334C884F");
+
+        Decoder::default().handle_manufacturer_data(&metrics, addr, &payload);
+
+        let snapshot = take_snapshot();
+        assert!(
+            gauge_value(&snapshot, "ruuvi_humidity_ratio", &[("device", addr)])
+                .is_some_and(|v| (v - 1.05).abs() < 1e-9)
+        );
+        assert_eq!(
+            None,
+            gauge_value(&snapshot, "ruuvi_dew_point_celsius", &[("device", addr)])
+        );
+    }
+
     #[test]
     fn manufacturer_data_records_v5_metrics() {
         let _guard = crate::test_utils::metrics::guard();
@@ -356,7 +1026,7 @@ mod tests {
         let payload_hex = "0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F";
         let payload = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
 
-        handle_manufacturer_data(&metrics, addr, &payload);
+        Decoder::default().handle_manufacturer_data(&metrics, addr, &payload);
 
         let decoded = match ruuvi_decoders::decode(payload_hex).expect("decode V5 frame") {
             RuuviData::V5(data) => data,
@@ -428,7 +1098,7 @@ mod tests {
                 .is_some_and(|v| (v - motion.tx_power.unwrap()).abs() < f64::EPSILON)
         );
         assert!(
-            gauge_value(&snapshot, "ruuvi_movecount_total", &[("device", addr)])
+            gauge_value(&snapshot, "ruuvi_movecount", &[("device", addr)])
                 .is_some_and(|v| (v - motion.movement_count.unwrap()).abs() < f64::EPSILON)
         );
         assert!(
@@ -438,4 +1108,583 @@ mod tests {
                 )
         );
     }
+
+    #[test]
+    fn manufacturer_data_records_e1_light_and_sound() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "aa:bb:cc:dd:ee:ff";
+        let payload = hex_literal::hex!(
+            "E1170C5668C79E0065007004BD11CA00C90A0213E0AC646480DECDEE100000000000CBB8334C884F"
+        );
+
+        Decoder::default().handle_manufacturer_data(&metrics, addr, &payload);
+
+        let snapshot = take_snapshot();
+        assert!(
+            gauge_value(&snapshot, "ruuvi_illuminance_lux", &[("device", addr)])
+                .is_some_and(|v| (v - 13027.0).abs() < 1e-6)
+        );
+        let sound = |kind: &str| {
+            gauge_value(
+                &snapshot,
+                "ruuvi_sound_dba",
+                &[("device", addr), ("kind", kind)],
+            )
+        };
+        assert!(sound("instant").is_some_and(|v| (v - 58.2).abs() < 1e-6));
+        assert!(sound("average").is_some_and(|v| (v - 58.0).abs() < 1e-6));
+        assert!(sound("peak").is_some_and(|v| (v - 69.2).abs() < 1e-6));
+    }
+
+    #[test]
+    fn e1_sound_marks_all_bits_set_as_invalid() {
+        let payload = hex_literal::hex!(
+            "E1170C5668C79E0065007004BD11CA00C90A0213E0ACFFFFFFDECDEE380000000000CBB8334C884F"
+        );
+        let data = match ruuvi_decoders::decode(
+            "E1170C5668C79E0065007004BD11CA00C90A0213E0ACFFFFFFDECDEE380000000000CBB8334C884F",
+        )
+        .expect("decode E1 frame")
+        {
+            RuuviData::E1(data) => data,
+            _ => panic!("unexpected format"),
+        };
+
+        let readings = E1Frame {
+            data: &data,
+            raw: &payload,
+        }
+        .light_sound()
+        .expect("light and sound");
+        assert_eq!(None, readings.sound_instant_dba);
+        assert_eq!(None, readings.sound_average_dba);
+        assert_eq!(None, readings.sound_peak_dba);
+    }
+
+    #[test]
+    fn manufacturer_data_records_v3_metrics() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "aa:bb:cc:dd:ee:ff";
+        let payload = hex_literal::hex!("03291A1ECE1EFC18F94202CA0B53");
+
+        Decoder::default().handle_manufacturer_data(&metrics, addr, &payload);
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", addr), ("format", "3")]
+            )
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &[("device", addr)])
+                .is_some_and(|v| (v - 26.3).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_pressure_hpa", &[("device", addr)])
+                .is_some_and(|v| (v - 1027.66).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(
+                &snapshot,
+                "ruuvi_acceleration_g",
+                &[("device", addr), ("axis", "Y")]
+            )
+            .is_some_and(|v| (v + 1.726).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_battery_volts", &[("device", addr)])
+                .is_some_and(|v| (v - 2.899).abs() < 1e-6)
+        );
+    }
+
+    #[test]
+    fn manufacturer_data_records_c5_metrics() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "aa:bb:cc:dd:ee:ff";
+        let payload = hex_literal::hex!("C512FC5394C37CAC364200CDCBB8334C884F");
+
+        Decoder::default().handle_manufacturer_data(&metrics, addr, &payload);
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", addr), ("format", "C5")]
+            )
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_humidity_ratio", &[("device", addr)])
+                .is_some_and(|v| (v - 0.5349).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_movecount", &[("device", addr)])
+                .is_some_and(|v| (v - 66.0).abs() < f64::EPSILON)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_seqno_current", &[("device", addr)])
+                .is_some_and(|v| (v - 205.0).abs() < f64::EPSILON)
+        );
+        assert_eq!(
+            None,
+            gauge_value(
+                &snapshot,
+                "ruuvi_acceleration_g",
+                &[("device", addr), ("axis", "X")]
+            )
+        );
+    }
+
+    #[test]
+    fn manufacturer_data_records_decrypted_v8_metrics() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "aa:bb:cc:dd:ee:ff";
+        let mut decoder = Decoder::new(BTreeMap::from([(addr.to_string(), formats::tests::KEY)]));
+
+        decoder.handle_manufacturer_data(
+            &metrics,
+            addr,
+            &formats::tests::encrypted_v8_frame(&formats::tests::KEY),
+        );
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", addr), ("format", "8")]
+            )
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &[("device", addr)])
+                .is_some_and(|v| (v - 24.3).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_seqno_current", &[("device", addr)])
+                .is_some_and(|v| (v - 205.0).abs() < f64::EPSILON)
+        );
+    }
+
+    #[test]
+    fn undecryptable_v8_frames_are_counted() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let frame = formats::tests::encrypted_v8_frame(&formats::tests::KEY);
+        let mut decoder = Decoder::new(BTreeMap::from([("aa:bb".to_string(), [0xAA; 16])]));
+
+        decoder.handle_manufacturer_data(&metrics, "aa:bb", &frame);
+        decoder.handle_manufacturer_data(&metrics, "cc:dd", &frame);
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_decryption_errors_total",
+                &[("device", "aa:bb"), ("reason", "crc")]
+            )
+        );
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_decryption_errors_total",
+                &[("device", "cc:dd"), ("reason", "no_key")]
+            )
+        );
+        decoder.handle_manufacturer_data(&metrics, "aa:bb", &frame[..frame.len() - 1]);
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &take_snapshot(),
+                "ruuvi_decryption_errors_total",
+                &[("device", "aa:bb"), ("reason", "length")]
+            )
+        );
+        assert_eq!(
+            None,
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", "aa:bb"), ("format", "8")]
+            )
+        );
+    }
+
+    #[test]
+    fn bthome_data_records_metrics_with_vendor_label() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "aa:bb:cc:dd:ee:ff";
+        let payload = hex_literal::hex!("400009016102290903931504138A01");
+
+        Decoder::default().handle_bthome_data(&metrics, addr, &payload);
+
+        let snapshot = take_snapshot();
+        let labels = [("device", addr), ("vendor", "bthome")];
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[
+                    ("device", addr),
+                    ("vendor", "bthome"),
+                    ("format", "bthome_v2")
+                ]
+            )
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &labels)
+                .is_some_and(|v| (v - 23.45).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_humidity_ratio", &labels)
+                .is_some_and(|v| (v - 0.5523).abs() < 1e-6)
+        );
+        assert!(gauge_value(&snapshot, "ruuvi_dew_point_celsius", &labels).is_some());
+        assert!(
+            gauge_value(&snapshot, "ruuvi_battery_percent", &labels)
+                .is_some_and(|v| (v - 97.0).abs() < f64::EPSILON)
+        );
+        assert_eq!(
+            None,
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &[("device", addr)])
+        );
+    }
+
+    #[test]
+    fn xiaomi_data_records_metrics_with_vendor_label() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "a4:c1:38:4d:6c:d3";
+        let payload = hex_literal::hex!("D36C4D38C1A4F6081B15A00B5A7A04");
+
+        Decoder::default().handle_xiaomi_data(&metrics, addr, &payload);
+
+        let snapshot = take_snapshot();
+        let labels = [("device", addr), ("vendor", "xiaomi")];
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", addr), ("vendor", "xiaomi"), ("format", "pvvx")]
+            )
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &labels)
+                .is_some_and(|v| (v - 22.94).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_battery_volts", &labels)
+                .is_some_and(|v| (v - 2.976).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_battery_percent", &labels)
+                .is_some_and(|v| (v - 90.0).abs() < f64::EPSILON)
+        );
+    }
+
+    #[test]
+    fn calibration_is_applied_before_derived_values() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "ca:11:b0:00:00:01";
+        let calibration = Calibration {
+            temperature: Correction {
+                offset: -0.4,
+                gain: 1.0,
+            },
+            humidity: Correction {
+                offset: 3.0,
+                gain: 1.0,
+            },
+            co2: Correction {
+                offset: 5000.0,
+                gain: 1.0,
+            },
+            ..Default::default()
+        };
+        let mut decoder =
+            Decoder::default().with_calibrations(BTreeMap::from([(addr.to_string(), calibration)]));
+
+        decoder.handle_manufacturer_data(
+            &metrics,
+            addr,
+            &hex_literal::hex!(
+                "E1170C5668C79E0065007004BD11CA00C90A0213E0AC646480DECDEE100000000000CBB8334C884F"
+            ),
+        );
+
+        let snapshot = take_snapshot();
+        // The E1 frame reads 29.5°C, 55.3%RH and 201 ppm CO2.
+        let dew_point = dew_point_celsius(29.1, 0.583).unwrap();
+        assert!(
+            gauge_value(&snapshot, "ruuvi_temperature_celsius", &[("device", addr)])
+                .is_some_and(|v| (v - 29.1).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_dew_point_celsius", &[("device", addr)])
+                .is_some_and(|v| (v - dew_point).abs() < 1e-6)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_air_quality_index", &[("device", addr)])
+                .is_some_and(|v| v.abs() < f64::EPSILON)
+        );
+    }
+
+    #[test]
+    fn enabled_derived_metrics_are_recorded() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "de:00:00:00:00:01";
+        let mut decoder = Decoder::default().with_derived_metrics(DerivedMetrics {
+            vapour_pressure_deficit: true,
+            air_density: true,
+            ..Default::default()
+        });
+
+        decoder.handle_manufacturer_data(
+            &metrics,
+            addr,
+            &hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F"),
+        );
+
+        let snapshot = take_snapshot();
+        let labels = [("device", addr)];
+        assert!(
+            gauge_value(&snapshot, "ruuvi_vapour_pressure_deficit_kpa", &labels)
+                .is_some_and(|v| (v - 1.41).abs() < 0.01)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_air_density_kg_m3", &labels)
+                .is_some_and(|v| (v - 1.17).abs() < 0.01)
+        );
+        assert_eq!(
+            None,
+            gauge_value(&snapshot, "ruuvi_absolute_humidity_g_m3", &labels)
+        );
+        assert_eq!(None, gauge_value(&snapshot, "ruuvi_humidex", &labels));
+    }
+
+    #[test]
+    fn sea_level_pressure_uses_device_altitude_over_global_one() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let frame = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
+        let mut decoder = Decoder::default().with_altitudes(
+            BTreeMap::from([("a1:00:00:00:00:01".to_string(), 120.0)]),
+            Some(540.0),
+        );
+
+        decoder.handle_manufacturer_data(&metrics, "a1:00:00:00:00:01", &frame);
+        decoder.handle_manufacturer_data(&metrics, "a1:00:00:00:00:02", &frame);
+        Decoder::default().handle_manufacturer_data(&metrics, "a1:00:00:00:00:03", &frame);
+
+        let snapshot = take_snapshot();
+        let sea_level = |device| {
+            gauge_value(
+                &snapshot,
+                "ruuvi_pressure_sea_level_hpa",
+                &[("device", device)],
+            )
+        };
+        assert!(sea_level("a1:00:00:00:00:01").is_some_and(|v| (v - 1014.31).abs() < 0.01));
+        assert!(sea_level("a1:00:00:00:00:02").is_some_and(|v| (v - 1064.08).abs() < 0.01));
+        assert_eq!(None, sea_level("a1:00:00:00:00:03"));
+    }
+
+    #[test]
+    fn missed_and_duplicate_frames_are_counted() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "5e:00:00:00:00:01";
+        let mut decoder = Decoder::default();
+
+        // sequence numbers 205, 205 and 208
+        let frame = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
+        decoder.handle_manufacturer_data(&metrics, addr, &frame);
+        decoder.handle_manufacturer_data(&metrics, addr, &frame);
+        let frame = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200D0CBB8334C884F");
+        decoder.handle_manufacturer_data(&metrics, addr, &frame);
+
+        let snapshot = take_snapshot();
+        let labels = [("device", addr), ("format", "5")];
+        assert_eq!(
+            Some(2),
+            counter_value(&snapshot, "ruuvi_frames_total", &labels)
+        );
+        assert_eq!(
+            Some(1),
+            counter_value(&snapshot, "ruuvi_frames_duplicate_total", &labels)
+        );
+        assert_eq!(
+            Some(2),
+            counter_value(&snapshot, "ruuvi_frames_missed_total", &labels)
+        );
+        assert_eq!(
+            Some(208.0),
+            gauge_value(&snapshot, "ruuvi_seqno_current", &[("device", addr)])
+        );
+    }
+
+    #[test]
+    fn movements_are_counted_monotonically_across_reboots() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "40:00:00:00:00:01";
+        let mut decoder = Decoder::default();
+
+        // (movement counter, sequence number): (66, 205), (68, 206), then the
+        // tag reboots and sends (1, 0)
+        for frame in [
+            hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F"),
+            hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364400CECBB8334C884F"),
+            hex_literal::hex!("0512FC5394C37C0004FFFC040CAC36010000CBB8334C884F"),
+        ] {
+            decoder.handle_manufacturer_data(&metrics, addr, &frame);
+        }
+
+        let snapshot = take_snapshot();
+        let labels = [("device", addr)];
+        assert_eq!(
+            Some(2),
+            counter_value(&snapshot, "ruuvi_movements_total", &labels)
+        );
+        assert_eq!(
+            Some(1.0),
+            gauge_value(&snapshot, "ruuvi_movecount", &labels)
+        );
+    }
+
+    #[test]
+    fn reboot_from_upper_half_of_sequence_is_not_a_gap() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "40:00:00:00:00:02";
+        let mut decoder = Decoder::default();
+
+        // (movement counter, sequence number): (120, 40000), then the tag
+        // reboots and sends (0, 0)
+        for frame in [
+            hex_literal::hex!("0512FC5394C37C0004FFFC040CAC36789C40CBB8334C884F"),
+            hex_literal::hex!("0512FC5394C37C0004FFFC040CAC36000000CBB8334C884F"),
+        ] {
+            decoder.handle_manufacturer_data(&metrics, addr, &frame);
+        }
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            0,
+            counter_value(&snapshot, "ruuvi_movements_total", &[("device", addr)]).unwrap_or(0)
+        );
+        assert_eq!(
+            0,
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_missed_total",
+                &[("device", addr), ("format", "5")]
+            )
+            .unwrap_or(0)
+        );
+    }
+
+    #[test]
+    fn tilt_and_orientation_changes_are_recorded() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let addr = "07:00:00:00:00:01";
+        let mut decoder = Decoder::default().with_orientation_threshold(45.0);
+
+        // lying flat with (0.004, -0.004, 1.036) g, then tipped over onto
+        // its side with (0.0, 1.0, 0.0) g
+        for frame in [
+            hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F"),
+            hex_literal::hex!("0512FC5394C37C000003E80000AC364200CECBB8334C884F"),
+        ] {
+            decoder.handle_manufacturer_data(&metrics, addr, &frame);
+        }
+
+        let snapshot = take_snapshot();
+        let labels = [("device", addr)];
+        assert_eq!(
+            Some(1),
+            counter_value(&snapshot, "ruuvi_orientation_changes_total", &labels)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_roll_degrees", &labels)
+                .is_some_and(|v| (v - 90.0).abs() < 0.01)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_pitch_degrees", &labels).is_some_and(|v| v.abs() < 0.01)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_acceleration_magnitude_g", &labels)
+                .is_some_and(|v| (v - 1.0).abs() < 0.001)
+        );
+    }
+
+    #[test]
+    fn battery_state_is_compensated_by_temperature() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let mut decoder = Decoder::default();
+
+        // 2.4 V at 24.3°C on the shelf and at -22°C in the freezer
+        decoder.handle_manufacturer_data(
+            &metrics,
+            "ba:00:00:00:00:01",
+            &hex_literal::hex!("0512FC5394C37C0004FFFC040C6416420001CBB8334C884F"),
+        );
+        decoder.handle_manufacturer_data(
+            &metrics,
+            "ba:00:00:00:00:02",
+            &hex_literal::hex!("05EED05394C37C0004FFFC040C6416420001CBB8334C884F"),
+        );
+
+        let snapshot = take_snapshot();
+        let shelf = [("device", "ba:00:00:00:00:01")];
+        let freezer = [("device", "ba:00:00:00:00:02")];
+        assert_eq!(
+            Some(0.0),
+            gauge_value(&snapshot, "ruuvi_battery_percent", &shelf)
+        );
+        assert_eq!(
+            Some(1.0),
+            gauge_value(&snapshot, "ruuvi_battery_low", &shelf)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_battery_percent", &freezer)
+                .is_some_and(|v| (v - 40.0).abs() < 0.01)
+        );
+        assert_eq!(
+            Some(0.0),
+            gauge_value(&snapshot, "ruuvi_battery_low", &freezer)
+        );
+    }
 }
@@ -0,0 +1,295 @@
+//! Per-device tracking of the wrapping counters sent by the tags: detection of
+//! lost and repeated frames from measurement sequence numbers, and movements
+//! from the movement counter.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Tags count from 0 after booting. A sequence number below this, following
+/// one which was not about to wrap around, is a reboot rather than a gap.
+const REBOOT_SEQUENCE_LIMIT: u32 = 16;
+/// 8-bit counters wrap around every few minutes, so a number close to 0 after
+/// a gap is as likely a wrap-around with lost frames as a reboot.
+const REBOOT_MIN_MODULUS: u32 = 256;
+
+/// How far a sequence number may go backwards to still be considered an
+/// older frame, e.g. one received late by another adapter, instead of a restart.
+const LATE_WINDOW: u32 = 16;
+/// Late frames arrive shortly after the newer one. Later, going backwards is
+/// a restart, e.g. of a tag rebooting within its first frames.
+const LATE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How a sequence number relates to the previous one of the same device and format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SequenceStep {
+    /// Same measurement as before, e.g. a repeated advertisement.
+    Duplicate,
+    /// A measurement older than the previous one.
+    Late,
+    /// The counter advanced, `missed` frames in between were not received.
+    Advanced { missed: u32 },
+    /// First frame of the device, or a jump that looks like a restart of the device.
+    Restarted,
+}
+
+/// Last sequence number per device and format. Devices sending several formats
+/// (e.g. 6 and E1 of the Ruuvi Air) use independent counters for each of them.
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTracker {
+    last: BTreeMap<(String, &'static str), (u32, Instant)>,
+}
+
+impl SequenceTracker {
+    /// `modulus` is the number of distinct values of the counter before it
+    /// wraps around. Gaps of more than half of it are treated as a restart,
+    /// since the counter may also have been reset or have jumped backwards,
+    /// unless it went back by only a few frames. Restarts from 0 of counters
+    /// wider than 8 bits are also detected when the previous number was in
+    /// the upper half.
+    pub(crate) fn observe(
+        &mut self,
+        addr: &str,
+        format: &'static str,
+        seqno: u32,
+        modulus: u32,
+        at: Instant,
+    ) -> SequenceStep {
+        let key = (addr.to_string(), format);
+        let Some(&(last, last_at)) = self.last.get(&key) else {
+            self.last.insert(key, (seqno, at));
+            return SequenceStep::Restarted;
+        };
+        let recent = at.saturating_duration_since(last_at) < LATE_TIMEOUT;
+        let distance = (seqno % modulus + modulus - last % modulus) % modulus;
+        let step = match distance {
+            0 => SequenceStep::Duplicate,
+            d if d <= modulus / 2 => {
+                if modulus > REBOOT_MIN_MODULUS
+                    && seqno < REBOOT_SEQUENCE_LIMIT
+                    && d > seqno + REBOOT_SEQUENCE_LIMIT
+                {
+                    SequenceStep::Restarted
+                } else {
+                    SequenceStep::Advanced { missed: d - 1 }
+                }
+            }
+            d if recent && modulus - d <= LATE_WINDOW.min(modulus / 4) => SequenceStep::Late,
+            _ => SequenceStep::Restarted,
+        };
+        if step != SequenceStep::Late {
+            self.last.insert(key, (seqno, at));
+        }
+        step
+    }
+}
+
+/// Last movement counter per device, to turn it into a monotonic count of movements.
+#[derive(Debug, Default)]
+pub(crate) struct MovementTracker {
+    last: BTreeMap<String, u32>,
+}
+
+impl MovementTracker {
+    /// Returns the movements since the previous frame of the device, taking
+    /// the wrap-around of the counter into account. The first frame only sets
+    /// the baseline.
+    pub(crate) fn observe(&mut self, addr: &str, count: u32, modulus: u32) -> u32 {
+        match self.last.insert(addr.to_string(), count) {
+            Some(last) => (count % modulus + modulus - last % modulus) % modulus,
+            None => 0,
+        }
+    }
+
+    /// Forgets the baseline after a reboot of the tag, which resets its counter.
+    pub(crate) fn reset(&mut self, addr: &str) {
+        self.last.remove(addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_and_repeated_frames_are_detected() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        assert_eq!(
+            SequenceStep::Restarted,
+            tracker.observe("aa", "5", 10, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Duplicate,
+            tracker.observe("aa", "5", 10, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Advanced { missed: 0 },
+            tracker.observe("aa", "5", 11, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Advanced { missed: 3 },
+            tracker.observe("aa", "5", 15, 65535, now)
+        );
+    }
+
+    #[test]
+    fn counters_wrap_around_at_their_width() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        tracker.observe("aa", "6", 254, 256, now);
+        assert_eq!(
+            SequenceStep::Advanced { missed: 1 },
+            tracker.observe("aa", "6", 0, 256, now)
+        );
+
+        // 0xFFFF is reserved for "not available" in format 5
+        tracker.observe("aa", "5", 65534, 65535, now);
+        assert_eq!(
+            SequenceStep::Advanced { missed: 0 },
+            tracker.observe("aa", "5", 0, 65535, now)
+        );
+    }
+
+    #[test]
+    fn devices_and_formats_are_tracked_separately() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        tracker.observe("aa", "6", 7, 256, now);
+        assert_eq!(
+            SequenceStep::Restarted,
+            tracker.observe("aa", "E1", 7, 0xFF_FFFF, now)
+        );
+        assert_eq!(
+            SequenceStep::Restarted,
+            tracker.observe("bb", "6", 7, 256, now)
+        );
+        assert_eq!(
+            SequenceStep::Duplicate,
+            tracker.observe("aa", "6", 7, 256, now)
+        );
+    }
+
+    #[test]
+    fn large_jumps_are_treated_as_restart() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        tracker.observe("aa", "5", 5000, 65535, now);
+        assert_eq!(
+            SequenceStep::Restarted,
+            tracker.observe("aa", "5", 3, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Advanced { missed: 0 },
+            tracker.observe("aa", "5", 4, 65535, now)
+        );
+    }
+
+    #[test]
+    fn counting_from_zero_is_a_restart() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        tracker.observe("aa", "5", 40000, 65535, now);
+        assert_eq!(
+            SequenceStep::Restarted,
+            tracker.observe("aa", "5", 0, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Advanced { missed: 0 },
+            tracker.observe("aa", "5", 1, 65535, now)
+        );
+
+        // a real wrap-around close to the end of the range
+        tracker.observe("bb", "5", 65530, 65535, now);
+        assert_eq!(
+            SequenceStep::Advanced { missed: 6 },
+            tracker.observe("bb", "5", 2, 65535, now)
+        );
+    }
+
+    #[test]
+    fn eight_bit_counters_wrap_around_with_gaps() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        tracker.observe("aa", "6", 230, 256, now);
+        assert_eq!(
+            SequenceStep::Advanced { missed: 35 },
+            tracker.observe("aa", "6", 10, 256, now)
+        );
+    }
+
+    #[test]
+    fn frames_received_late_are_not_a_restart() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        tracker.observe("aa", "5", 10, 65535, now);
+        tracker.observe("aa", "5", 12, 65535, now);
+        assert_eq!(
+            SequenceStep::Late,
+            tracker.observe("aa", "5", 11, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Late,
+            tracker.observe("aa", "5", 10, 65535, now)
+        );
+        assert_eq!(
+            SequenceStep::Advanced { missed: 0 },
+            tracker.observe("aa", "5", 13, 65535, now)
+        );
+
+        tracker.observe("aa", "6", 1, 256, now);
+        assert_eq!(
+            SequenceStep::Late,
+            tracker.observe("aa", "6", 255, 256, now)
+        );
+    }
+
+    #[test]
+    fn going_back_after_a_while_is_a_restart() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        // reboot within the first frames of the previous boot
+        tracker.observe("aa", "5", 10, 65535, now);
+        assert_eq!(
+            SequenceStep::Late,
+            tracker.observe("aa", "5", 0, 65535, now + Duration::from_secs(1))
+        );
+        assert_eq!(
+            SequenceStep::Restarted,
+            tracker.observe("aa", "5", 1, 65535, now + Duration::from_secs(3))
+        );
+        assert_eq!(
+            SequenceStep::Advanced { missed: 0 },
+            tracker.observe("aa", "5", 2, 65535, now + Duration::from_secs(4))
+        );
+    }
+
+    #[test]
+    fn movements_are_counted_across_wrap_around() {
+        let mut movements = MovementTracker::default();
+
+        assert_eq!(0, movements.observe("aa", 250, 255));
+        assert_eq!(3, movements.observe("aa", 253, 255));
+        // 255 is reserved for "not available", the counter wraps from 254 to 0
+        assert_eq!(3, movements.observe("aa", 1, 255));
+        assert_eq!(0, movements.observe("aa", 1, 255));
+    }
+
+    #[test]
+    fn reboot_resets_movement_baseline() {
+        let mut movements = MovementTracker::default();
+
+        movements.observe("aa", 120, 255);
+        movements.reset("aa");
+
+        assert_eq!(0, movements.observe("aa", 0, 255));
+        assert_eq!(2, movements.observe("aa", 2, 255));
+    }
+}
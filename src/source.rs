@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::SystemTime;
+
+use bluer::{Uuid, UuidExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::bthome::bthome_uuid;
+use crate::capture::CaptureWriter;
+use crate::metrics::Metrics;
+use crate::ruuvi::Decoder;
+use crate::xiaomi::environmental_sensing_uuid;
+
+pub(crate) const RUUVI_COMPANY_ID: u16 = 0x0499;
+pub(crate) const ADVERTISEMENT_QUEUE_SIZE: usize = 256;
+
+const AD_TYPE_SERVICE_DATA_16: u8 = 0x16;
+const AD_TYPE_MANUFACTURER_SPECIFIC_DATA: u8 = 0xFF;
+
+/// A single advertisement as received by any source, independent of BlueZ.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Advertisement {
+    pub address: String,
+    pub adapter: Option<String>,
+    pub gateway: Option<String>,
+    pub rssi: Option<i16>,
+    pub manufacturer_data: HashMap<u16, Vec<u8>>,
+    pub service_data: HashMap<Uuid, Vec<u8>>,
+    pub received_at: SystemTime,
+}
+
+impl Advertisement {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            adapter: None,
+            gateway: None,
+            rssi: None,
+            manufacturer_data: HashMap::new(),
+            service_data: HashMap::new(),
+            received_at: SystemTime::now(),
+        }
+    }
+
+    /// Splits a raw advertisement into its AD structures and collects the
+    /// manufacturer specific and service data, like BlueZ does.
+    pub fn with_raw_data(mut self, raw: &[u8]) -> Self {
+        let mut rest = raw;
+        while let Some((&len, tail)) = rest.split_first() {
+            let len = usize::from(len);
+            if len == 0 || len > tail.len() {
+                break;
+            }
+            let (structure, next) = tail.split_at(len);
+            match structure {
+                [AD_TYPE_MANUFACTURER_SPECIFIC_DATA, lo, hi, payload @ ..] => {
+                    self.manufacturer_data
+                        .insert(u16::from_le_bytes([*lo, *hi]), payload.to_vec());
+                }
+                [AD_TYPE_SERVICE_DATA_16, lo, hi, payload @ ..] => {
+                    self.service_data.insert(
+                        Uuid::from_u16(u16::from_le_bytes([*lo, *hi])),
+                        payload.to_vec(),
+                    );
+                }
+                _ => {}
+            }
+            rest = next;
+        }
+        self
+    }
+
+    pub fn is_ruuvi(&self) -> bool {
+        self.manufacturer_data.contains_key(&RUUVI_COMPANY_ID)
+    }
+}
+
+pub(crate) type AdvertisementSender = mpsc::Sender<Advertisement>;
+pub(crate) type AdvertisementReceiver = mpsc::Receiver<Advertisement>;
+
+/// Anything that produces advertisements, e.g. the BlueZ monitor or a Ruuvi Gateway.
+pub(crate) trait AdvertisementSource: Send + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Forwards advertisements into `sink` until the source is exhausted or fails.
+    fn run(self, sink: AdvertisementSender) -> impl Future<Output = bluer::Result<()>> + Send;
+}
+
+pub(crate) fn spawn_source<S: AdvertisementSource>(
+    source: S,
+    sink: AdvertisementSender,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let name = source.name();
+        if let Err(err) = source.run(sink).await {
+            eprintln!("Advertisement source {} failed: {}", name, err);
+        }
+    })
+}
+
+pub(crate) fn handle_advertisement(
+    metrics: &Metrics,
+    decoder: &mut Decoder,
+    advertisement: &Advertisement,
+) {
+    let addr = advertisement.address.as_str();
+    if let Some(reason) = decoder.filter().rejects(addr) {
+        metrics.inc_frames_dropped(reason);
+        return;
+    }
+    decoder.liveness().seen(addr, SystemTime::now());
+    if let Some(rssi) = advertisement.rssi {
+        metrics.set_signal_rssi(addr, advertisement.adapter.as_deref(), f64::from(rssi));
+    }
+    if let Some(value) = advertisement.manufacturer_data.get(&RUUVI_COMPANY_ID) {
+        if let Some(gateway) = &advertisement.gateway {
+            metrics.inc_gateway_frames(addr, gateway);
+            if let Some(rssi) = advertisement.rssi {
+                metrics.set_gateway_rssi(addr, gateway, f64::from(rssi));
+            }
+        }
+        decoder.handle_manufacturer_data(metrics, addr, value);
+    }
+    if let Some(value) = advertisement.service_data.get(&bthome_uuid()) {
+        decoder.handle_bthome_data(metrics, addr, value);
+    }
+    if let Some(value) = advertisement
+        .service_data
+        .get(&environmental_sensing_uuid())
+    {
+        decoder.handle_xiaomi_data(metrics, addr, value);
+    }
+    metrics.set_device_info(addr);
+    metrics.update_rust_and_process_start_time(); // otherwise the metrics are removed after the idle timeout
+}
+
+/// Decodes advertisements from all sources until every sender is dropped,
+/// optionally recording those that pass the device filter to a capture file first.
+pub(crate) async fn process_advertisements(
+    mut advertisements: AdvertisementReceiver,
+    metrics: Metrics,
+    mut decoder: Decoder,
+    mut capture: Option<CaptureWriter>,
+) {
+    while let Some(advertisement) = advertisements.recv().await {
+        if let Some(writer) = capture.as_mut()
+            && decoder.filter().rejects(&advertisement.address).is_none()
+            && let Err(err) = writer.record(&advertisement)
+        {
+            eprintln!("Error writing capture: {}", err);
+        }
+        handle_advertisement(&metrics, &mut decoder, &advertisement);
+    }
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Gateways report MAC addresses in upper case, the `device` label is lower case.
+pub(crate) fn normalize_device_address(mac: &str) -> String {
+    mac.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{DeviceFilter, parse_patterns};
+    use crate::test_utils::metrics::{clear, counter_value, gauge_value, take_snapshot};
+    use crate::test_utils::source::InMemorySource;
+
+    const PAYLOAD: [u8; 24] = hex_literal::hex!("0512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F");
+
+    #[test]
+    fn hex_is_decoded() {
+        assert_eq!(Some(vec![0x02, 0x01, 0xff]), decode_hex("0201ff"));
+        assert_eq!(Some(vec![0xab]), decode_hex("AB"));
+        assert_eq!(None, decode_hex("abc"));
+        assert_eq!(None, decode_hex("zz"));
+    }
+
+    #[test]
+    fn raw_advertisement_is_split_into_ad_structures() {
+        let raw = hex_literal::hex!(
+            "0201061BFF99040512FC5394C37C0004FFFC040CAC364200CDCBB8334C884F0516D2FC4001"
+        );
+
+        let advertisement = Advertisement::new("aa:bb").with_raw_data(&raw);
+
+        assert_eq!(
+            Some(&PAYLOAD.to_vec()),
+            advertisement.manufacturer_data.get(&RUUVI_COMPANY_ID)
+        );
+        assert_eq!(
+            Some(&vec![0x40, 0x01]),
+            advertisement.service_data.get(&Uuid::from_u16(0xFCD2))
+        );
+        assert!(advertisement.is_ruuvi());
+    }
+
+    #[test]
+    fn truncated_advertisement_is_ignored() {
+        let raw = hex_literal::hex!("0201061BFF9904");
+
+        let advertisement = Advertisement::new("aa:bb").with_raw_data(&raw);
+
+        assert!(advertisement.manufacturer_data.is_empty());
+        assert!(!advertisement.is_ruuvi());
+    }
+
+    #[test]
+    fn gateway_advertisement_records_gateway_metrics() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let mut advertisement = Advertisement::new("aa:bb");
+        advertisement.gateway = Some("gw".to_string());
+        advertisement.rssi = Some(-70);
+        advertisement
+            .manufacturer_data
+            .insert(RUUVI_COMPANY_ID, PAYLOAD.to_vec());
+
+        handle_advertisement(&metrics, &mut Decoder::default(), &advertisement);
+
+        let snapshot = take_snapshot();
+        let labels = [("device", "aa:bb"), ("gateway", "gw")];
+        assert_eq!(
+            Some(1),
+            counter_value(&snapshot, "ruuvi_gateway_frames_total", &labels)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_gateway_rssi_dbm", &labels)
+                .is_some_and(|v| (v + 70.0).abs() < f64::EPSILON)
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_rssi_dbm", &[("device", "aa:bb")])
+                .is_some_and(|v| (v + 70.0).abs() < f64::EPSILON)
+        );
+    }
+
+    #[test]
+    fn filtered_devices_are_dropped_before_any_metric() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let mut decoder = Decoder::default().with_filter(DeviceFilter {
+            allow: parse_patterns("f1:*"),
+            deny: parse_patterns("f1:00"),
+        });
+        for addr in ["f1:00", "f2:00", "f1:01"] {
+            let mut advertisement = Advertisement::new(addr);
+            advertisement.rssi = Some(-70);
+            advertisement
+                .manufacturer_data
+                .insert(RUUVI_COMPANY_ID, PAYLOAD.to_vec());
+            handle_advertisement(&metrics, &mut decoder, &advertisement);
+        }
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_dropped_total",
+                &[("reason", "denied")]
+            )
+        );
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_dropped_total",
+                &[("reason", "not_allowed")]
+            )
+        );
+        assert_eq!(
+            None,
+            gauge_value(&snapshot, "ruuvi_rssi_dbm", &[("device", "f1:00")])
+        );
+        assert_eq!(
+            None,
+            gauge_value(&snapshot, "ruuvi_rssi_dbm", &[("device", "f2:00")])
+        );
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", "f1:01"), ("format", "5")]
+            )
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn filtered_devices_are_not_captured() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let dir = std::env::temp_dir().join(format!(
+            "ruuvi-prometheus-rs-capture-filter-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.jsonl");
+        let capture = CaptureWriter::open(&path, 1024 * 1024, 2).unwrap();
+        let decoder = Decoder::default().with_filter(DeviceFilter {
+            allow: Vec::new(),
+            deny: parse_patterns("f3:00"),
+        });
+        let frames = ["f3:00", "f3:01"].map(|addr| {
+            let mut advertisement = Advertisement::new(addr);
+            advertisement
+                .manufacturer_data
+                .insert(RUUVI_COMPANY_ID, PAYLOAD.to_vec());
+            advertisement
+        });
+
+        let (sink, advertisements) = mpsc::channel(ADVERTISEMENT_QUEUE_SIZE);
+        spawn_source(InMemorySource::new(frames.to_vec()), sink);
+        process_advertisements(advertisements, metrics, decoder, Some(capture)).await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(1, content.lines().count());
+        assert!(!content.contains("f3:00"));
+        assert!(content.contains("f3:01"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn in_memory_source_is_decoded_end_to_end() {
+        let _guard = crate::test_utils::metrics::guard();
+        clear();
+        let metrics = Metrics::register();
+        let mut frame = Advertisement::new("aa:bb");
+        frame
+            .manufacturer_data
+            .insert(RUUVI_COMPANY_ID, PAYLOAD.to_vec());
+        let mut rssi = Advertisement::new("aa:bb");
+        rssi.rssi = Some(-33);
+
+        let (sink, advertisements) = mpsc::channel(ADVERTISEMENT_QUEUE_SIZE);
+        spawn_source(InMemorySource::new(vec![frame.clone(), rssi, frame]), sink);
+        process_advertisements(advertisements, metrics, Decoder::default(), None).await;
+
+        let snapshot = take_snapshot();
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_total",
+                &[("device", "aa:bb"), ("format", "5")]
+            )
+        );
+        assert_eq!(
+            Some(1),
+            counter_value(
+                &snapshot,
+                "ruuvi_frames_duplicate_total",
+                &[("device", "aa:bb"), ("format", "5")]
+            )
+        );
+        assert!(
+            gauge_value(&snapshot, "ruuvi_rssi_dbm", &[("device", "aa:bb")])
+                .is_some_and(|v| (v + 33.0).abs() < f64::EPSILON)
+        );
+    }
+}
@@ -84,3 +84,33 @@ pub mod metrics {
         })
     }
 }
+
+pub mod source {
+    use crate::source::{Advertisement, AdvertisementSender, AdvertisementSource};
+
+    /// Replays a fixed list of advertisements, for tests without bluetoothd.
+    pub struct InMemorySource {
+        advertisements: Vec<Advertisement>,
+    }
+
+    impl InMemorySource {
+        pub fn new(advertisements: Vec<Advertisement>) -> Self {
+            Self { advertisements }
+        }
+    }
+
+    impl AdvertisementSource for InMemorySource {
+        fn name(&self) -> &'static str {
+            "in-memory"
+        }
+
+        async fn run(self, sink: AdvertisementSender) -> bluer::Result<()> {
+            for advertisement in self.advertisements {
+                if sink.send(advertisement).await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Decoder for Xiaomi thermometers (e.g. LYWSD03MMC) running the pvvx or
+//! ATC1441 custom firmware, which advertise in the Environmental Sensing
+//! service data.
+
+use bluer::{Uuid, UuidExt};
+
+use crate::calibration::Calibration;
+use crate::metrics::Metrics;
+
+pub(crate) const ENVIRONMENTAL_SENSING_UUID16: u16 = 0x181A;
+pub(crate) const VENDOR_XIAOMI: &str = "xiaomi";
+
+const PVVX_PAYLOAD_LENGTH: usize = 15;
+const ATC1441_PAYLOAD_LENGTH: usize = 13;
+
+pub(crate) fn environmental_sensing_uuid() -> Uuid {
+    Uuid::from_u16(ENVIRONMENTAL_SENSING_UUID16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum XiaomiFormat {
+    Pvvx,
+    Atc1441,
+}
+
+impl XiaomiFormat {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            XiaomiFormat::Pvvx => "pvvx",
+            XiaomiFormat::Atc1441 => "atc1441",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct XiaomiData {
+    pub format: XiaomiFormat,
+    /// Temperature in Celsius
+    pub temperature: f64,
+    /// Relative humidity in %
+    pub humidity: f64,
+    /// Battery voltage in mV
+    pub battery_voltage: u16,
+    /// Battery level in %
+    pub battery_level: u8,
+    pub frame_counter: u8,
+}
+
+impl XiaomiData {
+    pub(crate) fn calibrated(self, calibration: &Calibration) -> Self {
+        Self {
+            temperature: calibration.temperature.apply(self.temperature),
+            humidity: calibration.humidity.apply_humidity(self.humidity),
+            ..self
+        }
+    }
+}
+
+/// The pvvx format is little endian with 0.01 resolution, the older ATC1441
+/// format is big endian with 0.1°C and 1% resolution. Both start with the MAC.
+pub(crate) fn decode(value: &[u8]) -> Option<XiaomiData> {
+    match value.len() {
+        PVVX_PAYLOAD_LENGTH => Some(XiaomiData {
+            format: XiaomiFormat::Pvvx,
+            temperature: f64::from(i16::from_le_bytes([value[6], value[7]])) * 0.01,
+            humidity: f64::from(u16::from_le_bytes([value[8], value[9]])) * 0.01,
+            battery_voltage: u16::from_le_bytes([value[10], value[11]]),
+            battery_level: value[12],
+            frame_counter: value[13],
+        }),
+        ATC1441_PAYLOAD_LENGTH => Some(XiaomiData {
+            format: XiaomiFormat::Atc1441,
+            temperature: f64::from(i16::from_be_bytes([value[6], value[7]])) * 0.1,
+            humidity: f64::from(value[8]),
+            battery_level: value[9],
+            battery_voltage: u16::from_be_bytes([value[10], value[11]]),
+            frame_counter: value[12],
+        }),
+        _ => None,
+    }
+}
+
+pub(crate) fn apply_xiaomi_metrics(metrics: &Metrics, addr: &str, data: &XiaomiData) {
+    metrics.set_temperature(addr, data.temperature);
+    metrics.set_humidity(addr, data.humidity / 100.0);
+    metrics.set_voltage(addr, f64::from(data.battery_voltage) / 1000.0);
+    metrics.set_battery_percent(addr, f64::from(data.battery_level));
+    metrics.set_seqno(addr, f64::from(data.frame_counter));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pvvx_payload_is_decoded() {
+        let payload = hex_literal::hex!("D36C4D38C1A4F6081B15A00B5A7A04");
+
+        let data = decode(&payload).expect("decode pvvx");
+
+        assert_eq!(XiaomiFormat::Pvvx, data.format);
+        assert!((data.temperature - 22.94).abs() < 1e-9);
+        assert!((data.humidity - 54.03).abs() < 1e-9);
+        assert_eq!(2976, data.battery_voltage);
+        assert_eq!(90, data.battery_level);
+        assert_eq!(122, data.frame_counter);
+    }
+
+    #[test]
+    fn atc1441_payload_is_decoded() {
+        let payload = hex_literal::hex!("A4C1384D6CD300E536640BA02A");
+
+        let data = decode(&payload).expect("decode atc1441");
+
+        assert_eq!(XiaomiFormat::Atc1441, data.format);
+        assert!((data.temperature - 22.9).abs() < 1e-9);
+        assert!((data.humidity - 54.0).abs() < f64::EPSILON);
+        assert_eq!(100, data.battery_level);
+        assert_eq!(2976, data.battery_voltage);
+        assert_eq!(42, data.frame_counter);
+    }
+
+    #[test]
+    fn encrypted_or_unknown_payloads_are_skipped() {
+        assert_eq!(None, decode(&[0x00; 11]));
+        assert_eq!(None, decode(&[]));
+    }
+}